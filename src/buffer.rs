@@ -0,0 +1,276 @@
+//!
+//! When a collector is shared between threads as `Arc<Mutex<Collector<T>>>`, every
+//! `Collector::allocate` takes the global lock. An `AllocationBuffer` reserves a batch of
+//! addresses from the collector at once, and then allocates objects at those addresses
+//! without touching the global lock, until the batch runs out.
+//!
+//! Objects allocated by a buffer are staged in the buffer, and they are published into the
+//! collector before every collecting pass (step), or when the buffer is flushed or dropped.
+//! Before that they could not be accessed through the collector, but their addresses could be
+//! stored into other objects or added into root set freely. Published objects are the same as
+//! the ones created by `Collector::allocate`, except that they are treated as roots until the
+//! buffer is flushed, since the allocating thread may not have a chance to store them anywhere
+//! yet. `AllocationBuffer::flush` returns the locked collector, so the objects could be stored
+//! into other objects before any collecting pass has a chance to clean them up.
+//!
+//! So a long-lived buffer, e.g. one owned by a worker thread, must be flushed regularly, and
+//! the natural cadence is every time the thread stores its new objects into shared ones, which
+//! requires locking the collector anyway. Objects allocated since the last flush could never be
+//! cleaned up, and the list of pinned addresses keeps growing until the next flush.
+//!
+//! Reserved addresses occupy slots, so they count towards `slot_max`. A buffer also reserves a
+//! byte allowance for the sizes of its objects, which counts towards the byte budget set by
+//! `Collector::set_byte_max` until it is used up or the buffer is dropped. The allowance is
+//! reserved in batches as well, large enough for `batch` objects of the size of the object being
+//! allocated, or whatever is left in the budget.
+//!
+//! The collector locks each buffer's staging area briefly when publishing, so the lock order is
+//! always the collector first and then the staging area. A buffer never locks the collector
+//! while holding its own staging area.
+//!
+//! ```rust
+//! use std::sync::{Arc, Mutex};
+//! use std::thread;
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::buffer::AllocationBuffer;
+//!
+//! struct Leaf;
+//!
+//! impl Keep for Leaf {
+//!     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+//! }
+//!
+//! fn main() {
+//!     let collector = Arc::new(Mutex::new(Collector::new(1024)));
+//!     let handles: Vec<_> = (0..4)
+//!         .map(|_| {
+//!             let mut buffer = AllocationBuffer::new(Arc::clone(&collector), 16);
+//!             thread::spawn(move || {
+//!                 let mut leaves = Vec::new();
+//!                 for _ in 0..100 {
+//!                     leaves.push(buffer.allocate(Leaf).unwrap());
+//!                 }
+//!                 let mut collector = buffer.flush();
+//!                 for leaf in leaves {
+//!                     collector.add_root(leaf);
+//!                 }
+//!             })
+//!         })
+//!         .collect();
+//!     for handle in handles {
+//!         handle.join().unwrap();
+//!     }
+//!     let mut collector = collector.lock().unwrap();
+//!     collector.collect().unwrap();
+//!     assert_eq!(collector.alive_count(), 400);
+//! }
+//! ```
+
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{error::Error, hook::Event, Address, Collector, Keep, Slot};
+
+/// Objects allocated by a buffer but not published yet, along with their sizes.
+pub(crate) type Staging<T> = Arc<Mutex<Vec<(Address, T, usize)>>>;
+
+/// Handle which allocates objects at addresses reserved in batches. See module level document
+/// for details.
+pub struct AllocationBuffer<T: Keep> {
+    collector: Arc<Mutex<Collector<T>>>,
+    batch: usize,
+    reserved: Vec<Address>,
+    allowance: usize,
+    staging: Staging<T>,
+}
+
+impl<T: Keep> AllocationBuffer<T> {
+    /// Create a buffer allocating objects for `collector`, which reserves `batch` addresses
+    /// every time it runs out of reserved addresses. Panic if `batch` is zero.
+    pub fn new(collector: Arc<Mutex<Collector<T>>>, batch: usize) -> Self {
+        assert!(batch > 0, "batch must be positive");
+        let staging = Staging::default();
+        collector
+            .lock()
+            .unwrap()
+            .stagings
+            .push((Arc::clone(&staging), Vec::new()));
+        Self {
+            collector,
+            batch,
+            reserved: Vec::new(),
+            allowance: 0,
+            staging,
+        }
+    }
+
+    /// Return the collector this buffer allocates objects for.
+    pub fn collector(&self) -> &Arc<Mutex<Collector<T>>> {
+        &self.collector
+    }
+
+    /// Allocate an object with `value` at a reserved address. If there's no reserved address
+    /// or not enough byte allowance left, lock the collector and reserve another batch, which
+    /// may trigger a collecting pass. If no slot could be reserved, throw `Error::OutOfSlots`,
+    /// and if the byte budget could not afford the object, throw `Error::OutOfMemory`.
+    pub fn allocate(&mut self, value: T) -> Result<Address, Error> {
+        let size = value.size();
+        if self.reserved.is_empty() || self.allowance < size {
+            let mut collector = self.collector.lock().unwrap();
+            if self.reserved.is_empty() {
+                self.reserved = collector.reserve(self.batch)?;
+            }
+            if self.allowance < size {
+                let want = size.saturating_mul(self.batch);
+                self.allowance += collector.reserve_bytes(size - self.allowance, want)?;
+            }
+        }
+        let address = self.reserved.pop().expect("reserved batch is never empty");
+        self.allowance -= size;
+        self.staging
+            .lock()
+            .unwrap()
+            .push((address.clone(), value, size));
+        Ok(address)
+    }
+
+    /// Publish all objects allocated by this buffer into the collector, stop treating them as
+    /// roots, and return the locked collector. Objects not kept by any alive object when the
+    /// returned guard is dropped may be cleaned up by the next collecting pass.
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # use hulunbuir::buffer::AllocationBuffer;
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let collector = Arc::new(Mutex::new(Collector::new(64)));
+    /// let mut buffer = AllocationBuffer::new(Arc::clone(&collector), 8);
+    /// for _ in 0..10 {
+    ///     let kept = buffer.allocate(Leaf).unwrap();
+    ///     for _ in 0..4 {
+    ///         buffer.allocate(Leaf).unwrap();
+    ///     }
+    ///     // flush once per round, when the kept object is stored
+    ///     buffer.flush().add_root(kept);
+    /// }
+    /// // garbage of every round is collectable, while unflushed objects are not
+    /// let _pinned = buffer.allocate(Leaf).unwrap();
+    /// let mut collector = collector.lock().unwrap();
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.alive_count(), 11);
+    /// ```
+    pub fn flush(&mut self) -> MutexGuard<'_, Collector<T>> {
+        let mut collector = self.collector.lock().unwrap();
+        collector.unpin(&self.staging);
+        collector
+    }
+}
+
+impl<T: Keep> Drop for AllocationBuffer<T> {
+    fn drop(&mut self) {
+        let mut collector = match self.collector.lock() {
+            Ok(collector) => collector,
+            Err(_) => return,
+        };
+        collector.unpin(&self.staging);
+        for address in mem::take(&mut self.reserved) {
+            collector.slots.release(&address);
+        }
+        collector.reserved_bytes -= self.allowance;
+        let staging = &self.staging;
+        collector
+            .stagings
+            .retain(|(other, _)| !Arc::ptr_eq(other, staging));
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Reserve at most `count` addresses for an allocation buffer, and at least one of them. A
+    /// collecting pass is triggered in the same condition as `Collector::allocate`. If no slot
+    /// is available, throw `Error::OutOfSlots`.
+    fn reserve(&mut self, count: usize) -> Result<Vec<Address>, Error> {
+        if self.should_collect(0) {
+            self.collect()?;
+        }
+        let status = self.heap_status();
+        let available = status.slot_max.saturating_sub(status.alive_count);
+        if available == 0 {
+            self.hooks.fire(&Event::OutOfSlots(&status));
+            return Err(Error::OutOfSlots {
+                alive_count: status.alive_count,
+                slot_max: status.slot_max,
+            });
+        }
+        Ok((0..count.clamp(1, available))
+            .map(|_| self.slots.reserve())
+            .collect())
+    }
+
+    /// Reserve a byte allowance of `want` bytes for an allocation buffer, or all the bytes left
+    /// in the budget if it is less. A collecting pass is triggered in the same condition as
+    /// `Collector::allocate` an object of `size` bytes. If less than `size` bytes are left,
+    /// throw `Error::OutOfMemory`.
+    fn reserve_bytes(&mut self, size: usize, want: usize) -> Result<usize, Error> {
+        if self.should_collect(size) {
+            self.collect()?;
+        }
+        let status = self.heap_status();
+        let available = status.byte_max.saturating_sub(status.bytes);
+        if available < size {
+            self.hooks.fire(&Event::OutOfMemory(&status));
+            return Err(Error::OutOfMemory {
+                size,
+                bytes: status.bytes,
+                byte_max: status.byte_max,
+            });
+        }
+        let allowance = want.min(available);
+        self.reserved_bytes += allowance;
+        Ok(allowance)
+    }
+
+    /// Move objects staged in all allocation buffers into the heap, as if they are created by
+    /// `Collector::allocate`, and treat them as roots until their buffers are flushed. Their
+    /// sizes are moved from reserved byte allowances into the total size.
+    pub(crate) fn publish(&mut self) {
+        let mut published = Vec::new();
+        for (staging, pinned) in &mut self.stagings {
+            for (address, value, size) in mem::take(&mut *staging.lock().unwrap()) {
+                pinned.push(address.clone());
+                published.push((address, value, size));
+            }
+        }
+        for (address, value, size) in published {
+            self.reserved_bytes -= size;
+            self.bytes += size;
+            self.slots.fill(
+                &address,
+                Slot {
+                    mark: self.marking.is_some(),
+                    young: self.nursery.is_some(),
+                    finalize: false,
+                    size,
+                    content: value,
+                },
+            );
+            if let Some(nursery) = &mut self.nursery {
+                nursery.young.push(address.clone());
+            }
+            self.touch(&address);
+        }
+    }
+
+    /// Publish all staged objects, and stop treating the ones allocated by the buffer of
+    /// `staging` as roots.
+    fn unpin(&mut self, staging: &Staging<T>) {
+        self.publish();
+        for (other, pinned) in &mut self.stagings {
+            if Arc::ptr_eq(other, staging) {
+                pinned.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//!
+//! `Collector::collect` marks and sweeps the whole heap in one calling, so when a collector
+//! is shared between threads as `Arc<Mutex<Collector<T>>>`, every thread stops while one of
+//! them is collecting. A `Marker` moves collecting passes to a dedicated background thread,
+//! which performs the marking phase without holding the lock, so other threads could keep on
+//! calling `take`, `fill` and `allocate` while the heap is being marked.
+//!
+//! Objects are only accessible through the mutex, so the marker could not read them while
+//! marking. Instead, the collector maintains a snapshot of edges between objects once a marker
+//! is spawned: objects allocated, modified (through `Collector::get_mut`, and so `replace`,
+//! `update`, `Slot::take` and `Slot::fill`) or cleaned up are recorded, and their edges are
+//! refreshed when the next collecting pass starts. A collecting pass performed by marker is
+//! split into:
+//! 1. root scanning: lock the collector, refresh the edge snapshot, take root set and release
+//!    the lock
+//! 2. marking: trace the edge snapshot from root set without locking the collector
+//! 3. sweeping: lock the collector, set mark bits of the objects found in marking, finish
+//!    the objects reached in other ways during marking, e.g. added roots, upgraded weak
+//!    addresses, ephemeron values and finalizers, and clean up dead objects in one go
+//!
+//! Since the edge snapshot is exactly the heap when root set is scanned, every object
+//! reachable at that moment survives the collecting pass, as well as every object allocated
+//! during it, which is the same snapshot-at-the-beginning guarantee as
+//! `Collector::collect_step`. The price is the memory of the edge snapshot, and some
+//! bookkeeping on every modification.
+//!
+//! If another thread starts a collecting pass while marker is marking, e.g. by calling
+//! `Collector::collect` or by allocating on a full heap, the pass of marker is cancelled and
+//! the one of that thread is performed instead, so `Marker::wait` returns without error.
+//!
+//! ```rust
+//! use std::sync::{Arc, Mutex};
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::concurrent::Marker;
+//! use hulunbuir::slot::Slot;
+//!
+//! struct ListNode(i32, Option<Address>);
+//!
+//! impl Keep for ListNode {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         if let Some(tail) = &self.1 {
+//!             keep(tail)
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let collector = Arc::new(Mutex::new(Collector::new(128)));
+//!     let marker = Marker::spawn(Arc::clone(&collector));
+//!     let root = collector.lock().unwrap().allocate(Slot::new(ListNode(0, None))).unwrap();
+//!     collector.lock().unwrap().set_root(root);
+//!     let _orphan = collector.lock().unwrap().allocate(Slot::new(ListNode(1, None))).unwrap();
+//!     marker.request();
+//!     marker.wait().unwrap();
+//!     assert_eq!(collector.lock().unwrap().alive_count(), 1);
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crate::{error::Error, Address, Collector, Keep};
+
+/// Snapshot of edges between objects, which is read by marker without locking the collector.
+type Edges = Arc<RwLock<HashMap<Address, Vec<Address>>>>;
+
+/// State of a collector which has a marker.
+#[derive(Debug)]
+pub(crate) struct Concurrent {
+    edges: Edges,
+    /// Objects whose edges in `edges` may be outdated.
+    pub(crate) dirty: HashSet<Address>,
+    /// Whether the unfinished collecting pass is performed by marker.
+    active: bool,
+}
+
+impl<T> Collector<T> {
+    /// Record that the edges of object at `address` may have changed, if the collector has a
+    /// marker.
+    pub(crate) fn touch(&mut self, address: &Address) {
+        if let Some(concurrent) = &mut self.concurrent {
+            concurrent.dirty.insert(address.to_owned());
+        }
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Give up the collecting pass performed by marker, if there is one.
+    pub(crate) fn cancel_concurrent(&mut self) {
+        if let Some(concurrent) = &mut self.concurrent {
+            if concurrent.active {
+                concurrent.active = false;
+                self.abort_marking();
+            }
+        }
+    }
+
+    /// Build the edge snapshot for a marker, or return `None` if the collector already has one.
+    fn attach_marker(&mut self) -> Option<Edges> {
+        if self.concurrent.is_some() {
+            return None;
+        }
+        let edges = Edges::default();
+        self.concurrent = Some(Concurrent {
+            edges: Arc::clone(&edges),
+            dirty: self.slots.iter().map(|(address, _)| address).collect(),
+            active: false,
+        });
+        Some(edges)
+    }
+
+    fn detach_marker(&mut self) {
+        self.cancel_concurrent();
+        self.concurrent = None;
+    }
+
+    /// Start a collecting pass performed by marker, and return root set. If a collecting pass
+    /// started by `Collector::collect_step` is in progress, finish it and return `None`
+    /// instead.
+    fn start_concurrent(&mut self) -> Result<Option<Vec<Address>>, Error> {
+        if self.marking.is_some() {
+            while self.collect_step(usize::MAX)?.is_none() {}
+            return Ok(None);
+        }
+        self.publish();
+        let concurrent = self.concurrent.as_mut().unwrap();
+        let mut edges = concurrent.edges.write().unwrap();
+        for address in concurrent.dirty.drain() {
+            match self.slots.get(&address) {
+                Some(slot) => {
+                    let mut keep = Vec::new();
+                    slot.content
+                        .with_keep(|address| keep.push(address.to_owned()));
+                    edges.insert(address, keep);
+                }
+                None => {
+                    edges.remove(&address);
+                }
+            }
+        }
+        drop(edges);
+        concurrent.active = true;
+        self.begin_marking(Vec::new());
+        Ok(Some(self.trace_roots().cloned().collect()))
+    }
+
+    /// Finish the collecting pass performed by marker, given the objects `marked` by tracing
+    /// the edge snapshot, and the `missing` addresses not in it. Do nothing if the pass has
+    /// been cancelled.
+    fn finish_concurrent(
+        &mut self,
+        marked: HashSet<Address>,
+        missing: Vec<Address>,
+    ) -> Result<(), Error> {
+        match &mut self.concurrent {
+            Some(concurrent) if concurrent.active => concurrent.active = false,
+            _ => return Ok(()),
+        }
+        let marking = self.marking.as_mut().unwrap();
+        for address in &marked {
+            if let Some(slot) = self.slots.get_mut(address) {
+                if !slot.mark {
+                    slot.mark = true;
+                    marking.marked += 1;
+                }
+            }
+        }
+        // missing addresses are invalid, which are reported by `collect_step`
+        marking.stack.extend(missing);
+        // values inserted into ephemeron tables during marking are not in the edge snapshot
+        for (_, entries) in self.ephemerons.iter() {
+            for (key, value) in entries {
+                if marked.contains(key) {
+                    marking.stack.push(value.to_owned());
+                }
+            }
+        }
+        while self.collect_step(usize::MAX)?.is_none() {}
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Control {
+    requested: bool,
+    running: bool,
+    stopped: bool,
+    error: Option<Error>,
+}
+
+/// Lock `control` ignoring poisoning, which could not leave it inconsistent since every update of
+/// it is a plain assignment.
+fn lock(control: &Mutex<Control>) -> MutexGuard<'_, Control> {
+    control.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Handle of a background marking thread.
+///
+/// The thread is stopped and joined when the handle is dropped, after finishing the requested
+/// collecting pass if there is one.
+pub struct Marker {
+    control: Arc<(Mutex<Control>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Marker {
+    /// Spawn a marking thread working on `collector`. A collector could only have one marker
+    /// at a time, and this method panics if `collector` already has one.
+    ///
+    /// If a panic happens during a collecting pass, either in the marking thread or in another
+    /// thread holding the lock of `collector`, the pass is given up and `Error::Poisoned` is
+    /// thrown by `Marker::wait`, as well as by every later pass.
+    pub fn spawn<T>(collector: Arc<Mutex<Collector<T>>>) -> Self
+    where
+        T: Keep + Send + 'static,
+    {
+        let edges = collector
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .attach_marker()
+            .expect("collector already has a marker");
+        let control = Arc::new((Mutex::new(Control::default()), Condvar::new()));
+        let thread_control = Arc::clone(&control);
+        let thread = thread::spawn(move || {
+            let (control, condvar) = &*thread_control;
+            loop {
+                let mut state = lock(control);
+                while !state.requested && !state.stopped {
+                    state = condvar.wait(state).unwrap_or_else(PoisonError::into_inner);
+                }
+                if !state.requested {
+                    break;
+                }
+                state.requested = false;
+                state.running = true;
+                drop(state);
+
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| Self::run(&collector, &edges)))
+                        .unwrap_or(Err(Error::Poisoned));
+
+                let mut state = lock(control);
+                state.running = false;
+                state.error = result.err();
+                condvar.notify_all();
+            }
+            if let Ok(mut collector) = collector.lock() {
+                collector.detach_marker();
+            }
+        });
+        Self {
+            control,
+            thread: Some(thread),
+        }
+    }
+
+    fn run<T: Keep>(collector: &Mutex<Collector<T>>, edges: &Edges) -> Result<(), Error> {
+        // root scanning
+        let mut stack = match collector.lock()?.start_concurrent()? {
+            Some(roots) => roots,
+            None => return Ok(()),
+        };
+        // marking, without locking the collector
+        let mut marked = HashSet::new();
+        let mut missing = Vec::new();
+        let edges = edges.read()?;
+        while let Some(address) = stack.pop() {
+            if marked.contains(&address) {
+                continue;
+            }
+            match edges.get(&address) {
+                Some(keep) => stack.extend(keep.iter().cloned()),
+                None => missing.push(address.clone()),
+            }
+            marked.insert(address);
+        }
+        drop(edges);
+        // sweeping
+        collector.lock()?.finish_concurrent(marked, missing)
+    }
+
+    /// Request a collecting pass and return immediately. Requesting while a collecting pass is
+    /// running will start another one after it finishes.
+    pub fn request(&self) {
+        let (control, condvar) = &*self.control;
+        lock(control).requested = true;
+        condvar.notify_all();
+    }
+
+    /// Block current thread until there's neither requested nor running collecting pass. The
+    /// error thrown by the last finished collecting pass is re-thrown, if there is one.
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use std::thread;
+    /// # use hulunbuir::{Address, Collector, Error, Keep};
+    /// # use hulunbuir::concurrent::Marker;
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let collector = Arc::new(Mutex::new(Collector::<Leaf>::new(16)));
+    /// let marker = Marker::spawn(Arc::clone(&collector));
+    /// let poisoner = Arc::clone(&collector);
+    /// let _ = thread::spawn(move || {
+    ///     let _collector = poisoner.lock().unwrap();
+    ///     panic!("panic while holding the lock");
+    /// })
+    /// .join();
+    /// marker.request();
+    /// match marker.wait() {
+    ///     Err(Error::Poisoned) => {}
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn wait(&self) -> Result<(), Error> {
+        let (control, condvar) = &*self.control;
+        let mut state = lock(control);
+        while state.requested || state.running {
+            state = condvar.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Marker {
+    fn drop(&mut self) {
+        let (control, condvar) = &*self.control;
+        lock(control).stopped = true;
+        condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//!
+//! `Collector::dump` writes the object graph of a collector for inspecting, in either Graphviz
+//! DOT or JSON format. Every object in the heap is written, alive or not, along with its
+//! outgoing edges gathered by `Keep::with_keep` (or the cached kept list for taken `Slot`), and
+//! whether it is in root set. An optional label for each object could be provided by
+//! `Keep::label`. Addresses are written in their `Display` format, such as `#3.0`.
+//!
+//! # DOT format
+//!
+//! Each object is a node labeled with its address and label, and objects in root set are
+//! drawn with double border. Each kept address is an edge, and edges to invalid addresses
+//! point to nodes drawn in dashed border.
+//!
+//! # JSON format
+//!
+//! ```json
+//! {
+//!   "slot_max": 128,
+//!   "roots": ["#0.0"],
+//!   "objects": [
+//!     {"address": "#0.0", "root": true, "young": false, "label": "head", "edges": ["#1.0"]},
+//!     {"address": "#1.0", "root": false, "young": false, "label": null, "edges": []}
+//!   ]
+//! }
+//! ```
+//!
+//! * `slot_max`: current maximum number of objects
+//! * `roots`: addresses in root set
+//! * `objects`: every object in the heap, in the order of address index
+//!   * `address`: address of the object
+//!   * `root`: whether the object is in root set
+//!   * `young`: whether the object is in young generation, always `false` if generational
+//!     mode is disabled
+//!   * `label`: string returned by `Keep::label`, or `null`
+//!   * `edges`: addresses kept by the object, in the order `keep` is called
+//!
+//! ```rust
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::dump::DumpFormat;
+//!
+//! struct ListNode(i32, Option<Address>);
+//!
+//! impl Keep for ListNode {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         if let Some(tail) = &self.1 {
+//!             keep(tail)
+//!         }
+//!     }
+//!
+//!     fn label(&self) -> Option<String> {
+//!         Some(format!("node {}", self.0))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut collector = Collector::new(128);
+//!     let tail = collector.allocate(ListNode(1, None)).unwrap();
+//!     let head = collector.allocate(ListNode(0, Some(tail))).unwrap();
+//!     collector.set_root(head);
+//!     let mut dot = Vec::new();
+//!     collector.dump(&mut dot, DumpFormat::Dot).unwrap();
+//!     assert!(String::from_utf8(dot).unwrap().contains("\"#1.0\" -> \"#0.0\";"));
+//!     let mut json = Vec::new();
+//!     collector.dump(&mut json, DumpFormat::Json).unwrap();
+//!     assert!(String::from_utf8(json).unwrap().contains("\"label\": \"node 0\""));
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::{Address, Collector, Keep};
+
+/// Output format of `Collector::dump`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Graphviz DOT format.
+    Dot,
+    /// JSON format. See module level document for the schema.
+    Json,
+}
+
+fn edges<T: Keep>(object: &T) -> Vec<Address> {
+    let mut edges = Vec::new();
+    object.with_keep(|address| edges.push(address.to_owned()));
+    edges
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl<T: Keep> Collector<T> {
+    /// Write the object graph into `writer` in `format`. See module `dump` for details.
+    pub fn dump<W: Write>(&self, mut writer: W, format: DumpFormat) -> io::Result<()> {
+        match format {
+            DumpFormat::Dot => self.dump_dot(&mut writer),
+            DumpFormat::Json => self.dump_json(&mut writer),
+        }
+    }
+
+    fn dump_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph hulunbuir {{")?;
+        let mut dangling = HashSet::new();
+        for (address, slot) in self.slots.iter() {
+            let mut label = address.to_string();
+            if let Some(object_label) = slot.content.label() {
+                label = format!("{}\n{}", label, object_label);
+            }
+            let periphery = if self.roots.contains_key(&address) {
+                ", peripheries=2"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "    \"{}\" [label=\"{}\"{}];",
+                address,
+                escape(&label),
+                periphery
+            )?;
+            for edge in edges(&slot.content) {
+                if self.slots.get(&edge).is_none() {
+                    dangling.insert(edge.clone());
+                }
+                writeln!(writer, "    \"{}\" -> \"{}\";", address, edge)?;
+            }
+        }
+        for address in self.roots.keys() {
+            if self.slots.get(address).is_none() {
+                dangling.insert(address.to_owned());
+            }
+        }
+        for address in dangling {
+            writeln!(writer, "    \"{}\" [style=dashed];", address)?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    fn dump_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let list = |addresses: &mut dyn Iterator<Item = &Address>| {
+            let quoted: Vec<_> = addresses
+                .map(|address| format!("\"{}\"", address))
+                .collect();
+            format!("[{}]", quoted.join(", "))
+        };
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"slot_max\": {},", self.slot_max)?;
+        writeln!(writer, "  \"roots\": {},", list(&mut self.roots.keys()))?;
+        write!(writer, "  \"objects\": [")?;
+        for (index, (address, slot)) in self.slots.iter().enumerate() {
+            let label = match slot.content.label() {
+                Some(label) => format!("\"{}\"", escape(&label)),
+                None => "null".to_string(),
+            };
+            writeln!(writer, "{}", if index == 0 { "" } else { "," })?;
+            write!(
+                writer,
+                "    {{\"address\": \"{}\", \"root\": {}, \"young\": {}, \"label\": {}, \"edges\": {}}}",
+                address,
+                self.roots.contains_key(&address),
+                slot.young,
+                label,
+                list(&mut edges(&slot.content).iter())
+            )?;
+        }
+        writeln!(writer, "\n  ]")?;
+        writeln!(writer, "}}")
+    }
+}
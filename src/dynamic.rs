@@ -0,0 +1,314 @@
+//!
+//! A `Collector<T>` holds objects of exactly one type `T`. A language runtime with strings,
+//! closures and tables, for example, has to wrap all of them into one enum, which should be
+//! known by every module that adds a managed type. This module provides `Trace`, an
+//! object-safe version of `Keep`, so a single collector could manage objects of any type
+//! behind `Box<dyn Trace>`.
+//!
+//! # Heterogeneous heap
+//!
+//! Every type that implements `Keep` (and `Any` and `Send`) implements `Trace` automatically,
+//! and `Box<dyn Trace>` implements `Keep` in turn. So nothing special is required to create a
+//! `Collector<Box<dyn Trace>>`, and all methods of collector work on it as usual. In addition,
+//! methods suffixed with `_any` allocate objects with their concrete types, and access them
+//! with checked downcasting.
+//!
+//! # Typed address
+//!
+//! Objects allocated by `Collector::allocate_any` are referred by `Gc<U>`, an address which
+//! carries the type of object, so the compiler could tell a text from a pair. `Gc<U>`
+//! dereferences to untyped `Address`, which could be passed to `keep` in `Keep::with_keep`,
+//! and untyped address could be converted back with `Collector::downcast`.
+//!
+//! ```rust
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::dynamic::{Gc, Trace};
+//!
+//! struct Text(String);
+//!
+//! impl Keep for Text {
+//!     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+//! }
+//!
+//! struct Pair(Gc<Text>, Gc<Text>);
+//!
+//! impl Keep for Pair {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         keep(&self.0);
+//!         keep(&self.1);
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut collector: Collector<Box<dyn Trace>> = Collector::new(128);
+//!     let hello = collector.allocate_any(Text("hello".to_string())).unwrap();
+//!     let world = collector.allocate_any(Text("world".to_string())).unwrap();
+//!     let pair = collector.allocate_any(Pair(hello.clone(), world)).unwrap();
+//!     collector.set_root(pair.clone().into());
+//!     let _orphan = collector.allocate_any(Text("orphan".to_string())).unwrap();
+//!     collector.collect().unwrap();
+//!     assert_eq!(collector.alive_count(), 3);
+//!
+//!     let text = collector.replace_any(&hello, Text("hi".to_string())).unwrap();
+//!     assert_eq!(text.0, "hello");
+//!     collector.get_any_mut(&hello).unwrap().0.push('!');
+//!     assert_eq!(collector.get_any(&hello).unwrap().0, "hi!");
+//!     // untyped address must be checked before being used as typed one
+//!     let root = collector.root().clone().unwrap();
+//!     assert!(collector.downcast::<Text>(&root).is_err());
+//!     let pair = collector.downcast::<Pair>(&root).unwrap();
+//!     let pair = collector.replace_any(&pair, Pair(hello.clone(), hello)).unwrap();
+//!     assert_eq!(collector.replace_any(&pair.1, Text("world".to_string())).unwrap().0, "world");
+//! }
+//! ```
+//!
+//! `Slot<Box<dyn Trace>>` works in the same way, with `Collector::take_any` and
+//! `Collector::fill_any`.
+
+use std::any::Any;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::{
+    error::Error,
+    slot::{Slot, Take},
+    Address, Collector, Keep,
+};
+
+/// Object-safe version of `Keep`, which is implemented for every `Keep` type automatically.
+pub trait Trace: Any + Send {
+    /// Call back `keep` with the addresses of objects that this object wishes to keep. See
+    /// `Keep::with_keep` for details.
+    fn trace(&self, keep: &mut dyn FnMut(&Address));
+
+    /// Return the number of bytes retained by this object. See `Keep::size` for details.
+    fn object_size(&self) -> usize;
+
+    /// Return a human readable label of this object. See `Keep::label` for details.
+    fn object_label(&self) -> Option<String>;
+
+    /// Upcast to `Any` for downcasting.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Upcast to mutable `Any` for downcasting.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Upcast to boxed `Any` for downcasting.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Keep + Any + Send> Trace for T {
+    fn trace(&self, keep: &mut dyn FnMut(&Address)) {
+        self.with_keep(keep)
+    }
+
+    fn object_size(&self) -> usize {
+        self.size()
+    }
+
+    fn object_label(&self) -> Option<String> {
+        self.label()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Keep for Box<dyn Trace> {
+    fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+        (**self).trace(&mut keep)
+    }
+
+    fn size(&self) -> usize {
+        (**self).object_size()
+    }
+
+    fn label(&self) -> Option<String> {
+        (**self).object_label()
+    }
+}
+
+/// Address of a managed object typed `U`.
+///
+/// See module level document for details.
+pub struct Gc<U> {
+    address: Address,
+    marker: PhantomData<fn() -> U>,
+}
+
+impl<U> Gc<U> {
+    fn new(address: Address) -> Self {
+        Self {
+            address,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the untyped address.
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+}
+
+impl<U> Deref for Gc<U> {
+    type Target = Address;
+
+    fn deref(&self) -> &Address {
+        &self.address
+    }
+}
+
+impl<U> From<Gc<U>> for Address {
+    fn from(gc: Gc<U>) -> Self {
+        gc.address
+    }
+}
+
+impl<U> Clone for Gc<U> {
+    fn clone(&self) -> Self {
+        Self::new(self.address.clone())
+    }
+}
+
+impl<U> PartialEq for Gc<U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<U> Eq for Gc<U> {}
+
+impl<U> Hash for Gc<U> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address.hash(state)
+    }
+}
+
+impl<U> fmt::Debug for Gc<U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Gc").field(&self.address).finish()
+    }
+}
+
+fn downcast<U: Any>(object: Box<dyn Trace>) -> Result<U, Box<dyn Trace>> {
+    if (*object).as_any().is::<U>() {
+        Ok(*object.into_any().downcast().unwrap())
+    } else {
+        Err(object)
+    }
+}
+
+impl Collector<Box<dyn Trace>> {
+    /// Create a new managed object with `value` of any type, and return its typed address.
+    /// See `Collector::allocate` for details.
+    pub fn allocate_any<U: Keep + Any + Send>(&mut self, value: U) -> Result<Gc<U>, Error> {
+        self.allocate(Box::new(value)).map(Gc::new)
+    }
+
+    /// Return `true` if object at `address` is typed `U`. If there's no object at `address`,
+    /// throw `Error::InvalidAddress`.
+    pub fn is<U: Any>(&self, address: &Address) -> Result<bool, Error> {
+        let slot = self
+            .slots
+            .get(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        Ok((*slot.content).as_any().is::<U>())
+    }
+
+    /// Convert `address` into typed address. If the object at `address` is not typed `U`,
+    /// throw `Error::TypeMismatch`.
+    pub fn downcast<U: Any>(&self, address: &Address) -> Result<Gc<U>, Error> {
+        if !self.is::<U>(address)? {
+            return Err(Error::TypeMismatch(address.to_owned()));
+        }
+        Ok(Gc::new(address.to_owned()))
+    }
+
+    /// Return a reference to object at `address`. If the object has been replaced with a
+    /// value of other type through untyped address, throw `Error::TypeMismatch`. See
+    /// `Collector::get` for details.
+    pub fn get_any<U: Any>(&self, address: &Gc<U>) -> Result<&U, Error> {
+        (**self.get(address)?)
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| Error::TypeMismatch(address.address().to_owned()))
+    }
+
+    /// Return a mutable reference to object at `address`. If the object has been replaced
+    /// with a value of other type through untyped address, throw `Error::TypeMismatch`. See
+    /// `Collector::get_mut` for details.
+    pub fn get_any_mut<U: Any>(&mut self, address: &Gc<U>) -> Result<&mut U, Error> {
+        self.downcast::<U>(address)?;
+        Ok((**self.get_mut(address)?)
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap())
+    }
+
+    /// Replace the value of object at `address` with `value`, and return the original value.
+    /// If the object has been replaced with a value of other type through untyped address,
+    /// throw `Error::TypeMismatch` and leave it untouched. See `Collector::replace` for details.
+    pub fn replace_any<U: Keep + Any + Send>(
+        &mut self,
+        address: &Gc<U>,
+        value: U,
+    ) -> Result<U, Error> {
+        self.downcast::<U>(address)?;
+        let object = self.replace(address, Box::new(value))?;
+        Ok(downcast(object).ok().unwrap())
+    }
+}
+
+impl Collector<Slot<Box<dyn Trace>>> {
+    /// Create a new managed object in slot with `value` of any type, and return its typed
+    /// address. See `Collector::allocate` for details.
+    pub fn allocate_any<U: Keep + Any + Send>(&mut self, value: U) -> Result<Gc<U>, Error> {
+        self.allocate(Slot::new(Box::new(value))).map(Gc::new)
+    }
+
+    /// Convert `address` into typed address. If the object at `address` is not typed `U`,
+    /// throw `Error::TypeMismatch`. If the object is taken out, `U` is trusted.
+    pub fn downcast<U: Any>(&self, address: &Address) -> Result<Gc<U>, Error> {
+        let slot = self
+            .slots
+            .get(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        if let Some(object) = slot.content.get() {
+            if !(**object).as_any().is::<U>() {
+                return Err(Error::TypeMismatch(address.to_owned()));
+            }
+        }
+        Ok(Gc::new(address.to_owned()))
+    }
+
+    /// Take the object at `address` out. If the object has been filled with a value of other
+    /// type through untyped address, throw `Error::TypeMismatch` and leave it untouched. See
+    /// `Collector::take` for details.
+    pub fn take_any<U: Any>(&mut self, address: &Gc<U>) -> Result<Take<U>, Error> {
+        self.downcast::<U>(address)?;
+        Ok(match self.take(address)? {
+            Take::Free(object) => Take::Free(downcast(object).ok().unwrap()),
+            Take::Busy(parker) => Take::Busy(parker),
+        })
+    }
+
+    /// Fill the hole at `address` with `value`. See `Collector::fill` for details.
+    pub fn fill_any<U: Keep + Any + Send>(
+        &mut self,
+        address: &Gc<U>,
+        value: U,
+    ) -> Result<(), Error> {
+        self.fill(address, Box::new(value))
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::{error::Error, heap::Heap, Address, Collector};
+
+/// Handle of an ephemeron table owned by a collector.
+///
+/// An ephemeron table maps managed objects to managed objects. Different from keeping the
+/// value in key object's `Keep::with_keep`, the table itself keeps neither keys nor values
+/// alive: a value is considered as alive only if its key is alive for other reason, and the
+/// entry is removed once its key is cleaned up.
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+pub struct EphemeronTable(Address);
+
+pub(crate) type Ephemerons = Heap<HashMap<Address, Address>>;
+
+impl<T> Collector<T> {
+    /// Create an empty ephemeron table.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let table = collector.create_ephemeron_table();
+    /// let alive_key = collector.allocate(Leaf).unwrap();
+    /// let alive_value = collector.allocate(Leaf).unwrap();
+    /// let dead_key = collector.allocate(Leaf).unwrap();
+    /// let dead_value = collector.allocate(Leaf).unwrap();
+    /// collector.set_root(alive_key.clone());
+    /// collector.ephemeron_insert(&table, alive_key.clone(), alive_value.clone()).unwrap();
+    /// collector.ephemeron_insert(&table, dead_key.clone(), dead_value).unwrap();
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.alive_count(), 2);
+    /// assert_eq!(collector.ephemeron_get(&table, &alive_key), Some(&alive_value));
+    /// assert_eq!(collector.ephemeron_get(&table, &dead_key), None);
+    /// ```
+    pub fn create_ephemeron_table(&mut self) -> EphemeronTable {
+        EphemeronTable(self.ephemerons.insert(HashMap::new()))
+    }
+
+    /// Remove ephemeron `table` with all its entries. Return `false` if the table has been
+    /// removed before.
+    pub fn remove_ephemeron_table(&mut self, table: &EphemeronTable) -> bool {
+        self.ephemerons.remove(&table.0).is_some()
+    }
+
+    /// Insert an entry into ephemeron `table`, and return the value previously associated with
+    /// `key` if there is one. If the table has been removed, or there's no object at `key` or
+    /// `value`, throw `Error::InvalidAddress`.
+    pub fn ephemeron_insert(
+        &mut self,
+        table: &EphemeronTable,
+        key: Address,
+        value: Address,
+    ) -> Result<Option<Address>, Error> {
+        let key_slot = self
+            .slots
+            .get(&key)
+            .ok_or_else(|| Error::InvalidAddress(key.clone()))?;
+        if self.slots.get(&value).is_none() {
+            return Err(Error::InvalidAddress(value));
+        }
+        if let Some(marking) = &mut self.marking {
+            if key_slot.mark {
+                marking.stack.push(value.clone());
+            }
+        }
+        let entries = self
+            .ephemerons
+            .get_mut(&table.0)
+            .ok_or_else(|| Error::InvalidAddress(table.0.clone()))?;
+        Ok(entries.insert(key, value))
+    }
+
+    /// Return the value associated with `key` in ephemeron `table`, or `None` if there's no
+    /// such entry or the table has been removed.
+    pub fn ephemeron_get(&self, table: &EphemeronTable, key: &Address) -> Option<&Address> {
+        self.ephemerons.get(&table.0)?.get(key)
+    }
+
+    /// Remove the entry of `key` from ephemeron `table`, and return its value. Return `None` if
+    /// there's no such entry or the table has been removed.
+    pub fn ephemeron_remove(&mut self, table: &EphemeronTable, key: &Address) -> Option<Address> {
+        self.ephemerons.get_mut(&table.0)?.remove(key)
+    }
+
+    /// Remove entries whose keys have been cleaned up.
+    pub(crate) fn clean_ephemerons(&mut self) {
+        let slots = &self.slots;
+        for (_, entries) in self.ephemerons.iter_mut() {
+            entries.retain(|key, _| slots.get(key).is_some());
+        }
+    }
+}
+
+/// Call `f` with every value whose key is `key` in any table.
+pub(crate) fn with_values<F: FnMut(&Address)>(ephemerons: &Ephemerons, key: &Address, mut f: F) {
+    for (_, entries) in ephemerons.iter() {
+        if let Some(value) = entries.get(key) {
+            f(value);
+        }
+    }
+}
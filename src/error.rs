@@ -1,15 +1,67 @@
+#![allow(non_local_definitions)]
+
+use std::sync::PoisonError;
+
+use crate::Address;
 
 /// Errors thrown by collector.
 #[derive(Debug, Fail)]
+#[non_exhaustive]
 pub enum Error {
     /// Alive objects count reaches `slot_max` passed to `Collector::new`, and no object
-    /// is collectable.
-    #[fail(display = "out of slots")]
-    OutOfSlots,
+    /// is collectable. The occupancy of the heap is `alive_count / slot_max`.
+    #[fail(display = "out of slots ({} of {} slots used)", alive_count, slot_max)]
+    OutOfSlots {
+        /// Number of objects in the heap.
+        alive_count: usize,
+        /// Maximum number of objects.
+        slot_max: usize,
+    },
+    /// The total size of objects reaches the byte budget set by `Collector::set_byte_max` with
+    /// the object being allocated, and no object is collectable.
+    #[fail(
+        display = "out of memory ({} bytes requested, {} of {} bytes used)",
+        size, bytes, byte_max
+    )]
+    OutOfMemory {
+        /// Size of the object being allocated.
+        size: usize,
+        /// Total size of objects in the heap.
+        bytes: usize,
+        /// The byte budget.
+        byte_max: usize,
+    },
     /// Trying to access object with invalid address.
-    #[fail(display = "invalid address")]
-    InvalidAddress,
+    #[fail(display = "invalid address {}", _0)]
+    InvalidAddress(Address),
+    /// Root set or finalization queue contains invalid address when collecting.
+    #[fail(display = "invalid root address {}", _0)]
+    InvalidRoot(Address),
+    /// Object at `from` keeps invalid address `to` when collecting, which is usually caused by
+    /// a buggy `Keep::with_keep` implementation.
+    #[fail(display = "object at {} keeps invalid address {}", from, to)]
+    DanglingEdge {
+        /// Address of the object which keeps `to`.
+        from: Address,
+        /// The invalid address.
+        to: Address,
+    },
     /// Calling `Collector::fill` on non-empty slot. See document of `slot` module for details.
-    #[fail(display = "duplicated filling")]
-    DuplicatedFilling,
-}
\ No newline at end of file
+    #[fail(display = "duplicated filling at {}", _0)]
+    DuplicatedFilling(Address),
+    /// Trying to access object as a type other than its actual type. See document of `dynamic`
+    /// module for details.
+    #[fail(display = "type mismatch at {}", _0)]
+    TypeMismatch(Address),
+    /// A thread panicked while holding the lock of a collector shared with `Marker`, or the
+    /// marking thread panicked during a collecting pass, so the collector may be left in an
+    /// inconsistent state.
+    #[fail(display = "collector is poisoned by a panicked thread")]
+    Poisoned,
+}
+
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::Poisoned
+    }
+}
@@ -0,0 +1,113 @@
+use std::mem;
+
+use crate::{error::Error, Address, Collector, Slot};
+
+impl<T> Collector<T> {
+    /// Register a finalizer for object at `address`. If there's no object at `address`,
+    /// throw `Error::InvalidAddress`.
+    ///
+    /// When an object with finalizer is found dead in a collecting pass, it is put into
+    /// finalization queue instead of being cleaned up, and its finalizer is unregistered.
+    /// Objects in finalization queue, as well as objects kept by them, stay alive until they
+    /// are drained by `Collector::drain_finalizable`, so the user could do cleanup job with
+    /// them and other objects.
+    ///
+    /// After draining, a finalized object is an ordinary dead object which will be cleaned up
+    /// in the next collecting pass. However, if the user stores its address into an alive
+    /// object during finalization, it is resurrected and will not be cleaned up as long as it
+    /// is reachable. Its finalizer will not run again unless it is registered again. Weak
+    /// addresses of the object keep valid until it is actually cleaned up.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let handle = collector.allocate(Leaf).unwrap();
+    /// collector.register_finalizer(&handle).unwrap();
+    /// collector.collect().unwrap();
+    /// // the object is queued instead of dropped
+    /// assert_eq!(collector.alive_count(), 1);
+    /// let finalizable = collector.drain_finalizable();
+    /// assert_eq!(finalizable, vec![handle]);
+    /// // do some cleanup job with `finalizable` here...
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.alive_count(), 0);
+    /// ```
+    pub fn register_finalizer(&mut self, address: &Address) -> Result<(), Error> {
+        let slot = self
+            .slots
+            .get_mut(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        if !slot.finalize {
+            slot.finalize = true;
+            self.finalizer_count += 1;
+        }
+        Ok(())
+    }
+
+    /// Unregister the finalizer of object at `address`. Return `false` if the object has no
+    /// finalizer registered. If there's no object at `address`, throw `Error::InvalidAddress`.
+    pub fn unregister_finalizer(&mut self, address: &Address) -> Result<bool, Error> {
+        let slot = self
+            .slots
+            .get_mut(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        let registered = mem::replace(&mut slot.finalize, false);
+        if registered {
+            self.finalizer_count -= 1;
+        }
+        Ok(registered)
+    }
+
+    /// Take all objects out of finalization queue, in the order they are found dead. See
+    /// `Collector::register_finalizer` for details.
+    pub fn drain_finalizable(&mut self) -> Vec<Address> {
+        mem::take(&mut self.finalizable)
+    }
+
+    /// Unregister the finalizer of every unmarked object with finalizer, and return their
+    /// addresses. Only young objects are checked if `young_only` is `true`. The objects should
+    /// be put into finalization queue when the collecting pass finishes, or be passed to
+    /// `Collector::restore_finalizers` if it is aborted.
+    pub(crate) fn find_finalizable(&mut self, young_only: bool) -> Vec<Address> {
+        if self.finalizer_count == 0 {
+            return Vec::new();
+        }
+        let mut queued = Vec::new();
+        let mut enqueue = |address: Address, slot: &mut Slot<T>| {
+            if slot.finalize && !slot.mark {
+                slot.finalize = false;
+                queued.push(address);
+            }
+        };
+        match (&self.nursery, young_only) {
+            (Some(nursery), true) => {
+                for address in &nursery.young {
+                    enqueue(address.to_owned(), self.slots.get_mut(address).unwrap());
+                }
+            }
+            _ => {
+                for (address, slot) in self.slots.iter_mut() {
+                    enqueue(address, slot);
+                }
+            }
+        }
+        self.finalizer_count -= queued.len();
+        queued
+    }
+
+    /// Register the finalizers unregistered by `Collector::find_finalizable` again.
+    pub(crate) fn restore_finalizers(&mut self, addresses: &[Address]) {
+        for address in addresses {
+            if let Some(slot) = self.slots.get_mut(address) {
+                if !slot.finalize {
+                    slot.finalize = true;
+                    self.finalizer_count += 1;
+                }
+            }
+        }
+    }
+}
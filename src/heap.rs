@@ -0,0 +1,192 @@
+use crate::Address;
+
+/// Slab storage of managed objects.
+///
+/// Vacant entries are reused through a free list. The generation of an entry is bumped each
+/// time its object is removed, so addresses of removed objects never match again. Entries could
+/// also be reserved, which are vacant but not in the free list, until they are filled or
+/// released.
+#[derive(Debug)]
+pub(crate) struct Heap<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<usize>,
+    len: usize,
+    reserved: usize,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    generation: usize,
+    value: Option<T>,
+}
+
+impl<T> Heap<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            reserved: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn reserved(&self) -> usize {
+        self.reserved
+    }
+
+    pub(crate) fn get(&self, address: &Address) -> Option<&T> {
+        match self.entries.get(address.index) {
+            Some(entry) if entry.generation == address.generation => entry.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, address: &Address) -> Option<&mut T> {
+        match self.entries.get_mut(address.index) {
+            Some(entry) if entry.generation == address.generation => entry.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, value: T) -> Address {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let entry = &mut self.entries[index];
+            entry.value = Some(value);
+            return Address {
+                index,
+                generation: entry.generation,
+            };
+        }
+        self.entries.push(Entry {
+            generation: 0,
+            value: Some(value),
+        });
+        Address {
+            index: self.entries.len() - 1,
+            generation: 0,
+        }
+    }
+
+    /// Reserve a vacant entry, whose address could be filled with `Heap::fill` later.
+    pub(crate) fn reserve(&mut self) -> Address {
+        self.reserved += 1;
+        if let Some(index) = self.free.pop() {
+            return Address {
+                index,
+                generation: self.entries[index].generation,
+            };
+        }
+        self.entries.push(Entry {
+            generation: 0,
+            value: None,
+        });
+        Address {
+            index: self.entries.len() - 1,
+            generation: 0,
+        }
+    }
+
+    /// Fill the reserved entry at `address` with `value`.
+    pub(crate) fn fill(&mut self, address: &Address, value: T) {
+        let entry = &mut self.entries[address.index];
+        debug_assert!(entry.generation == address.generation && entry.value.is_none());
+        entry.value = Some(value);
+        self.reserved -= 1;
+        self.len += 1;
+    }
+
+    /// Put the reserved entry at `address` back into the free list without filling it.
+    pub(crate) fn release(&mut self, address: &Address) {
+        let entry = &mut self.entries[address.index];
+        debug_assert!(entry.generation == address.generation && entry.value.is_none());
+        entry.generation += 1;
+        self.free.push(address.index);
+        self.reserved -= 1;
+    }
+
+    pub(crate) fn remove(&mut self, address: &Address) -> Option<T> {
+        let entry = self.entries.get_mut(address.index)?;
+        if entry.generation != address.generation {
+            return None;
+        }
+        let value = entry.value.take()?;
+        entry.generation += 1;
+        self.free.push(address.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Address, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let address = Address {
+                    index,
+                    generation: entry.generation,
+                };
+                entry.value.as_ref().map(|value| (address, value))
+            })
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (Address, &mut T)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let address = Address {
+                    index,
+                    generation: entry.generation,
+                };
+                entry.value.as_mut().map(|value| (address, value))
+            })
+    }
+
+    /// Keep only the objects that `f` returns `true` for, without reallocating the entries.
+    pub(crate) fn retain<F: FnMut(&Address, &mut T) -> bool>(&mut self, mut f: F) {
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            let address = Address {
+                index,
+                generation: entry.generation,
+            };
+            let retained = match &mut entry.value {
+                Some(value) => f(&address, value),
+                None => continue,
+            };
+            if !retained {
+                entry.value = None;
+                entry.generation += 1;
+                self.free.push(index);
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Iterate over every entry with its generation, including vacant ones.
+    #[cfg(feature = "serde")]
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (usize, Option<&T>)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.generation, entry.value.as_ref()))
+    }
+
+    /// Rebuild a heap from the entries yielded by `Heap::entries`, so that vacant entries are
+    /// reused and addresses keep their meaning.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_entries<I: IntoIterator<Item = (usize, Option<T>)>>(entries: I) -> Self {
+        let mut heap = Self::new();
+        for (index, (generation, value)) in entries.into_iter().enumerate() {
+            match value {
+                Some(_) => heap.len += 1,
+                None => heap.free.push(index),
+            }
+            heap.entries.push(Entry { generation, value });
+        }
+        heap
+    }
+}
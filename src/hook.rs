@@ -0,0 +1,105 @@
+//!
+//! Embedding applications could register hooks on a collector with `Collector::add_hook`, to
+//! run their own code around collecting passes, e.g. flushing caches before collecting, or
+//! recording metrics after it.
+//!
+//! Hooks are called while the collector is borrowed by the method that fires the event, and
+//! they are given nothing but the event, so they could never re-enter the collector. If a hook
+//! captures the collector through something like `Arc<Mutex<Collector<T>>>`, it must not lock
+//! it, otherwise a dead lock happens. Queue the work up and do it after the method returns
+//! instead.
+//!
+//! ```rust
+//! use std::sync::{Arc, Mutex};
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::hook::Event;
+//!
+//! struct Leaf;
+//!
+//! impl Keep for Leaf {
+//!     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+//! }
+//!
+//! fn main() {
+//!     let mut collector = Collector::new(1);
+//!     let swept = Arc::new(Mutex::new(Vec::new()));
+//!     let hook_swept = Arc::clone(&swept);
+//!     collector.add_hook(move |event| {
+//!         if let Event::Swept(address) = event {
+//!             hook_swept.lock().unwrap().push(Address::clone(address));
+//!         }
+//!     });
+//!     let orphan = collector.allocate(Leaf).unwrap();
+//!     let _ = collector.allocate(Leaf).unwrap();
+//!     assert_eq!(*swept.lock().unwrap(), vec![orphan]);
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::{
+    policy::HeapStatus,
+    stats::{CollectKind, CollectStats},
+    Address, Collector,
+};
+
+/// Events fired by collector.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A collecting pass is about to start. For incremental collecting pass, it is fired once
+    /// before the first step.
+    BeforeCollect(CollectKind),
+    /// A collecting pass is finished.
+    AfterCollect(&'a CollectStats),
+    /// The object at the address is cleaned up. It is fired during sweeping, so the object
+    /// could not be accessed any more.
+    Swept(&'a Address),
+    /// `Collector::allocate` is about to throw `Error::OutOfSlots`.
+    OutOfSlots(&'a HeapStatus),
+    /// `Collector::allocate` is about to throw `Error::OutOfMemory`.
+    OutOfMemory(&'a HeapStatus),
+}
+
+/// Identifier of a registered hook, which could be used to remove it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
+type Hook = Box<dyn FnMut(&Event) + Send>;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<(usize, Hook)>,
+    next_id: usize,
+}
+
+impl Hooks {
+    pub(crate) fn fire(&mut self, event: &Event) {
+        for (_, hook) in &mut self.hooks {
+            hook(event);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hooks({})", self.hooks.len())
+    }
+}
+
+impl<T> Collector<T> {
+    /// Register `hook`, which will be called with every event fired by this collector. Hooks
+    /// are called in the order they are registered. See module `hook` for details.
+    pub fn add_hook<F: FnMut(&Event) + Send + 'static>(&mut self, hook: F) -> HookId {
+        let id = self.hooks.next_id;
+        self.hooks.next_id += 1;
+        self.hooks.hooks.push((id, Box::new(hook)));
+        HookId(id)
+    }
+
+    /// Remove the hook registered with `id`. Return `false` if it has been removed before.
+    pub fn remove_hook(&mut self, id: &HookId) -> bool {
+        let count = self.hooks.hooks.len();
+        self.hooks.hooks.retain(|(hook_id, _)| *hook_id != id.0);
+        self.hooks.hooks.len() != count
+    }
+}
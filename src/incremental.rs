@@ -0,0 +1,281 @@
+use std::mem;
+use std::time::{Duration, Instant};
+
+use crate::{
+    ephemeron::{self, Ephemerons},
+    error::Error,
+    hook::Event,
+    stats::{CollectKind, CollectStats},
+    Address, Collector, Keep,
+};
+
+/// State of an unfinished collecting pass.
+///
+/// Objects with mark bit set and not in `stack` are black, objects in `stack` are gray, and
+/// the rest are white.
+#[derive(Debug)]
+pub(crate) struct Marking {
+    pub(crate) stack: Vec<Address>,
+    /// Objects with finalizer found dead in this pass, which are put into finalization queue
+    /// when the pass finishes.
+    pub(crate) finalized: Vec<Address>,
+    pub(crate) marked: usize,
+    pub(crate) mark_duration: Duration,
+    pub(crate) max_pause: Duration,
+    pub(crate) step_count: usize,
+}
+
+/// Call `f` with every address that object at `address` keeps alive when tracing, i.e. the
+/// addresses kept by `object`, and the values associated with `address` in ephemeron tables.
+pub(crate) fn trace<T: Keep, F: FnMut(&Address)>(
+    address: &Address,
+    object: &T,
+    ephemerons: &Ephemerons,
+    mut f: F,
+) {
+    object.with_keep(&mut f);
+    ephemeron::with_values(ephemerons, address, f);
+}
+
+impl<T> Collector<T> {
+    /// Return `true` if a collecting pass started by `Collector::collect_step` or a marker is
+    /// not finished.
+    pub fn is_marking(&self) -> bool {
+        self.marking.is_some()
+    }
+
+    /// Iterate over the addresses tracing starts from, i.e. root set, finalization queue and
+    /// objects allocated by allocation buffers which have not been flushed.
+    pub(crate) fn trace_roots(&self) -> impl Iterator<Item = &Address> {
+        self.roots
+            .keys()
+            .chain(&self.finalizable)
+            .chain(self.stagings.iter().flat_map(|(_, pinned)| pinned))
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Write barrier for incremental marking, which should be called before object at `address`
+    /// is modified. The referents of its current value are taken as gray, so everything
+    /// reachable at the beginning of the collecting pass survives it (snapshot-at-the-beginning).
+    /// The object itself is turned back to gray as well, so it will be scanned again with its
+    /// new value.
+    pub(crate) fn barrier(&mut self, address: &Address) {
+        self.touch(address);
+        if let Some(marking) = &mut self.marking {
+            if let Some(slot) = self.slots.get_mut(address) {
+                let stack = &mut marking.stack;
+                slot.content.with_keep(|address| {
+                    stack.push(address.to_owned());
+                });
+                slot.mark = false;
+                stack.push(address.to_owned());
+            }
+        }
+    }
+
+    /// Perform a part of collecting pass, which scans at most `budget` objects. Objects staged
+    /// in allocation buffers are published first. If there's no unfinished collecting pass, a
+    /// new one is started by taking every object in root set and finalization queue as gray.
+    /// Return the statistics of the pass if it finished in this call, otherwise `None`.
+    /// `Error::InvalidRoot` or `Error::DanglingEdge` will be thrown in the same situation as
+    /// `Collector::collect`, and the unfinished collecting pass is aborted.
+    ///
+    /// The collector could be used normally between two steps. Replacing an object (including
+    /// taking and filling a `Slot`) takes the objects it used to keep as gray and turns itself
+    /// back to gray, objects added into root set are taken as gray, and objects allocated during
+    /// the collecting pass are considered as alive.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(128);
+    /// let mut list = None;
+    /// for _ in 0..10 {
+    ///     list = Some(collector.allocate(ListNode(list)).unwrap());
+    /// }
+    /// collector.set_root(list.unwrap());
+    /// let _orphan = collector.allocate(ListNode(None)).unwrap();
+    /// let mut step_count = 1;
+    /// while collector.collect_step(4).unwrap().is_none() {
+    ///     step_count += 1;
+    /// }
+    /// assert_eq!(step_count, 3);
+    /// assert_eq!(collector.alive_count(), 10);
+    /// ```
+    ///
+    /// Moving a reference from an object not scanned yet into a scanned one keeps the referent
+    /// alive:
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Node(Vec<Address>);
+    /// # impl Keep for Node {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, keep: F) {
+    /// #         self.0.iter().for_each(keep)
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(128);
+    /// let b = collector.allocate(Node(Vec::new())).unwrap();
+    /// let a = collector.allocate(Node(vec![b.clone()])).unwrap();
+    /// let c = collector.allocate(Node(Vec::new())).unwrap();
+    /// let root = collector.allocate(Node(vec![a.clone(), c.clone()])).unwrap();
+    /// collector.set_root(root);
+    /// let _orphan = collector.allocate(Node(Vec::new())).unwrap();
+    /// // scan root and then `c`, while `a` is still gray
+    /// assert!(collector.collect_step(1).unwrap().is_none());
+    /// assert!(collector.collect_step(1).unwrap().is_none());
+    /// collector.update(&c, |node| node.0.push(b.clone())).unwrap();
+    /// collector.update(&a, |node| node.0.clear()).unwrap();
+    /// while collector.collect_step(1).unwrap().is_none() {}
+    /// assert_eq!(collector.alive_count(), 4);
+    /// assert!(collector.get(&b).is_ok());
+    /// ```
+    ///
+    /// The same holds when the objects are taken out of `Slot` and filled back across steps:
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # use hulunbuir::slot::{Slot, Take};
+    /// # struct Node(Vec<Address>);
+    /// # impl Keep for Node {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, keep: F) {
+    /// #         self.0.iter().for_each(keep)
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(128);
+    /// let b = collector.allocate(Slot::new(Node(Vec::new()))).unwrap();
+    /// let a = collector.allocate(Slot::new(Node(vec![b.clone()]))).unwrap();
+    /// let c = collector.allocate(Slot::new(Node(Vec::new()))).unwrap();
+    /// let root = collector.allocate(Slot::new(Node(vec![a.clone(), c.clone()]))).unwrap();
+    /// collector.set_root(root);
+    /// let _orphan = collector.allocate(Slot::new(Node(Vec::new()))).unwrap();
+    /// assert!(collector.collect_step(1).unwrap().is_none());
+    /// assert!(collector.collect_step(1).unwrap().is_none());
+    /// let (mut a_node, mut c_node) = match (collector.take(&a), collector.take(&c)) {
+    ///     (Ok(Take::Free(a_node)), Ok(Take::Free(c_node))) => (a_node, c_node),
+    ///     _ => unreachable!(),
+    /// };
+    /// c_node.0.push(a_node.0.pop().unwrap());
+    /// collector.fill(&a, a_node).unwrap();
+    /// // scan `a`, while `c` is still taken out
+    /// collector.collect_step(1).unwrap();
+    /// collector.fill(&c, c_node).unwrap();
+    /// while collector.collect_step(1).unwrap().is_none() {}
+    /// assert_eq!(collector.alive_count(), 4);
+    /// assert!(collector.get(&b).is_ok());
+    /// ```
+    pub fn collect_step(&mut self, budget: usize) -> Result<Option<CollectStats>, Error> {
+        let start = Instant::now();
+        self.cancel_concurrent();
+        self.publish();
+        if self.marking.is_none() {
+            let stack = self.trace_roots().cloned().collect();
+            self.begin_marking(stack);
+        }
+        self.marking.as_mut().unwrap().step_count += 1;
+
+        let mut scanned = 0;
+        loop {
+            scanned += match self.mark(budget - scanned) {
+                Ok(scanned) => scanned,
+                Err(error) => {
+                    self.abort_marking();
+                    return Err(error);
+                }
+            };
+            let marking = self.marking.as_mut().unwrap();
+            if !marking.stack.is_empty() {
+                marking.marked += scanned;
+                marking.mark_duration += start.elapsed();
+                marking.max_pause = marking.max_pause.max(start.elapsed());
+                return Ok(None);
+            }
+            let queued = self.find_finalizable(false);
+            if queued.is_empty() {
+                break;
+            }
+            let marking = self.marking.as_mut().unwrap();
+            marking.finalized.extend(queued.iter().cloned());
+            marking.stack.extend(queued);
+        }
+        let finalized = mem::take(&mut self.marking.as_mut().unwrap().finalized);
+        self.finalizable.extend(finalized);
+
+        let sweep_start = Instant::now();
+        let swept = self.sweep();
+        self.debug_verify("after");
+        self.after_collect();
+        let marking = self.marking.take().unwrap();
+        let stats = CollectStats {
+            kind: CollectKind::Full,
+            marked: marking.marked + scanned,
+            swept,
+            surviving: self.slots.len(),
+            mark_duration: marking.mark_duration + (sweep_start - start),
+            sweep_duration: sweep_start.elapsed(),
+            step_count: marking.step_count,
+            max_pause: marking.max_pause.max(start.elapsed()),
+            slot_max: self.slot_max,
+            bytes: self.bytes,
+        };
+        self.record(&stats);
+        Ok(Some(stats))
+    }
+
+    /// Start a collecting pass with gray objects in `stack`.
+    pub(crate) fn begin_marking(&mut self, stack: Vec<Address>) {
+        self.debug_verify("before");
+        self.hooks.fire(&Event::BeforeCollect(CollectKind::Full));
+        self.marking = Some(Marking {
+            stack,
+            finalized: Vec::new(),
+            marked: 0,
+            mark_duration: Duration::default(),
+            max_pause: Duration::default(),
+            step_count: 0,
+        });
+    }
+
+    /// Give up the unfinished collecting pass, register the finalizers of objects found dead
+    /// in it again, and clear every mark bit.
+    pub(crate) fn abort_marking(&mut self) {
+        let marking = self.marking.take().unwrap();
+        self.restore_finalizers(&marking.finalized);
+        for (_, slot) in self.slots.iter_mut() {
+            slot.mark = false;
+        }
+    }
+
+    /// Scan at most `budget` gray objects, and return the number of scanned objects.
+    fn mark(&mut self, budget: usize) -> Result<usize, Error> {
+        let stack = &mut self.marking.as_mut().unwrap().stack;
+        let mut scanned = 0;
+        while scanned < budget {
+            let address = match stack.pop() {
+                Some(address) => address,
+                None => break,
+            };
+            let slot = match self.slots.get_mut(&address) {
+                Some(slot) => slot,
+                None => return Err(self.invalid_reference(address)),
+            };
+            if slot.mark {
+                continue;
+            }
+            slot.mark = true;
+            trace(&address, &slot.content, &self.ephemerons, |address| {
+                stack.push(address.to_owned());
+            });
+            scanned += 1;
+        }
+        Ok(scanned)
+    }
+}
@@ -2,13 +2,13 @@
 //! multithreads, and collecting process may happen in any of them.
 //!
 //! Normally, reading or updating a managed object must lock global collector as well,
-//! which significantly decrease multithread performance. However, Hulunbuir does not provide
-//! common "read guard" and "write guard" interface; instead it only supports two functions:
-//! `allocate` and `replace`. The first one create a managed object, and may trigger a garbage
-//! collecting process if necessary; the second one replace the value of a managed object with
-//! a new one provided by argument. The global collector only have to be locked during replacing
-//! and the lock could be released when working thread owns the value. So the lock will not
-//! become the bottleneck of performance.
+//! which significantly decrease multithread performance. So besides borrowing objects in place
+//! with `get`, `get_mut` and `update`, Hulunbuir supports two functions: `allocate` and
+//! `replace`. The first one create a managed object, and may trigger a garbage collecting
+//! process if necessary; the second one replace the value of a managed object with a new one
+//! provided by argument. The global collector only have to be locked during replacing and the
+//! lock could be released when working thread owns the value. So the lock will not become the
+//! bottleneck of performance.
 //!
 //! Hulunbuir also provides `Slot` as higher level abstraction and interface.
 //!
@@ -37,12 +37,10 @@
 //!     let root = collector.allocate(ListNode(0, None)).unwrap();
 //!     collector.set_root(root.clone());
 //!     let tail = collector.allocate(ListNode(1, None)).unwrap();
-//!     // replace root node out with something not important
-//!     let mut root_node = collector.replace(&root, ListNode(42, None)).unwrap();
-//!     root_node.1 = Some(tail);
-//!     // replace root node back
-//!     let _ = collector.replace(&root, root_node).unwrap();
-//!     
+//!     // update root node in place
+//!     collector.update(&root, |root_node| root_node.1 = Some(tail)).unwrap();
+//!     assert_eq!(collector.get(&root).unwrap().0, 0);
+//!
 //!     let _orphan = collector.allocate(ListNode(2, None)).unwrap();
 //!     // before collecting...
 //!     assert_eq!(collector.alive_count(), 3);
@@ -52,61 +50,165 @@
 //! }
 //! ```
 //!
-//! This `replace`-based object updating strategy is suitable for simple single-thread usage.
-//! The collector will work correctly **only when no garbage collection happens when any
+//! Borrowing objects in place is always safe in single-thread usage, since the collector could
+//! not collect anything while it is borrowed. The `replace`-based object updating strategy,
+//! on the other hand, works correctly **only when no garbage collection happens when any
 //! "real" object is replaced out**, which means, when any of them *is* replaced out:
 //! * no explicit calling to `Collector::collect`
 //! * no calling to `Collector::allocate`, since it may trigger collection as well if there's
-//! no slot available
+//!   no slot available
 //!
 //! In multithreading context, none of above could be archieved since each thread has no idea
 //! about what the others are doing. So more complicated strategy must be introduced. Hulunbuir
 //! provides `slot` module for this purpose, but you are free to develop your own one.
 
+/// Allocation buffers reserving addresses in batches.
+pub mod buffer;
+/// Background marking thread for collectors shared between threads.
+pub mod concurrent;
+/// Heap dump in Graphviz DOT and JSON format.
+pub mod dump;
+/// Heterogeneous heap holding objects of different types.
+pub mod dynamic;
 /// Errors.
 pub mod error;
+/// Event hooks for embedding applications.
+pub mod hook;
+/// Pluggable strategies of when to collect.
+pub mod policy;
+/// Thread-safe collector with sharded slot storage.
+pub mod sharded;
+/// Collector shared between threads with blocking `take`.
+pub mod shared;
 /// Slot-based abstraction for automatic dependency caching and thread parking.
 pub mod slot;
+/// Serialization of collectors and importing objects between collectors.
+#[cfg(feature = "serde")]
+pub mod snapshot;
+/// Statistics of collecting passes.
+pub mod stats;
+/// Verification of dangling addresses in the heap.
+pub mod verify;
+
+mod ephemeron;
+mod finalize;
+mod heap;
+mod incremental;
+mod nursery;
+mod retain;
+mod weak;
 
 use std::collections::HashMap;
+use std::fmt;
 use std::mem;
-use std::time::Instant;
 
+pub use crate::ephemeron::EphemeronTable;
 pub use crate::error::Error;
+pub use crate::weak::WeakAddress;
+
+use crate::buffer::Staging;
+use crate::concurrent::Concurrent;
+use crate::ephemeron::Ephemerons;
+use crate::heap::Heap;
+use crate::hook::{Event, Hooks};
+use crate::incremental::Marking;
+use crate::nursery::Nursery;
+use crate::policy::{GcPolicy, Threshold};
+use crate::stats::{CollectStats, Statistics};
 
 #[macro_use]
 extern crate failure_derive;
 
-use log::info;
-
 /// Memory manager for allocation and garbage collection.
 ///
 /// See module level document for basic usage.
 #[derive(Debug)]
 pub struct Collector<T> {
-    slots: HashMap<Address, Slot<T>>,
+    slots: Heap<Slot<T>>,
     slot_max: usize,
-    next_id: usize,
+    bytes: usize,
+    byte_max: usize,
+    roots: HashMap<Address, usize>,
     root: Option<Address>,
+    nursery: Option<Nursery>,
+    marking: Option<Marking>,
+    finalizable: Vec<Address>,
+    finalizer_count: usize,
+    ephemerons: Ephemerons,
+    policy: Box<dyn GcPolicy>,
+    statistics: Statistics,
+    hooks: Hooks,
+    verify: bool,
+    stagings: Vec<(Staging<T>, Vec<Address>)>,
+    reserved_bytes: usize,
+    concurrent: Option<Concurrent>,
 }
 
 /// Virtual memory address token.
+///
+/// An address consists of the index of a slot and the generation of that slot. Slots are
+/// reused after their objects are collected, but the generation is bumped as well, so an
+/// address of a collected object stays invalid forever.
+///
+/// ```
+/// # use hulunbuir::{Address, Collector, Error, Keep};
+/// # struct Leaf;
+/// # impl Keep for Leaf {
+/// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+/// # }
+/// let mut collector = Collector::new(1);
+/// let stale = collector.allocate(Leaf).unwrap();
+/// // the only slot is reused after `stale` is collected
+/// let fresh = collector.allocate(Leaf).unwrap();
+/// assert!(collector.replace(&fresh, Leaf).is_ok());
+/// match collector.replace(&stale, Leaf) {
+///     Err(Error::InvalidAddress(address)) => assert_eq!(address, stale),
+///     _ => unreachable!(),
+/// }
+/// ```
 #[derive(Hash, PartialEq, Eq, Clone, Debug)]
-pub struct Address(usize);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Address {
+    index: usize,
+    generation: usize,
+}
 
 /// Required trait for managed objects' type.
 pub trait Keep {
-    /// When this method is called, it should calls back `keep` with the addresses of objects 
+    /// When this method is called, it should calls back `keep` with the addresses of objects
     /// that this object wishes to keep, one per calling. If current object is considered
     /// as alive in a garbage collecting pass (probably since this method is called), then
     /// all the kept objects will also be considered as alive.
     ///
     /// If this method is not implemented properly, such as not calling `keep` or calling it
-    /// with insufficient addresses, `Memory::InvalidAddress` may be thrown in arbitrary time
+    /// with insufficient addresses, `Error::DanglingEdge` may be thrown in arbitrary time
     /// in the future.
     ///
+    /// Objects referred by `WeakAddress` should not be passed to `keep`, since weak addresses
+    /// are not supposed to keep objects alive.
+    ///
     /// There's no reason for this method to fail. Please panic if you have to.
     fn with_keep<F: FnMut(&Address)>(&self, keep: F);
+
+    /// Return the number of bytes retained by this object, which is used to account the heap
+    /// against the budget set by `Collector::set_byte_max`. Return `mem::size_of_val(self)` by
+    /// default, so memory owned through pointers, such as the content of `Vec`, should be added
+    /// by implementors.
+    fn size(&self) -> usize {
+        mem::size_of_val(self)
+    }
+
+    /// Return a human readable label of this object, which is used by `Collector::dump`.
+    /// Return `None` by default.
+    fn label(&self) -> Option<String> {
+        None
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}.{}", self.index, self.generation)
+    }
 }
 
 impl<T> Collector<T> {
@@ -114,35 +216,109 @@ impl<T> Collector<T> {
     /// object typed `T`.
     pub fn new(slot_max: usize) -> Self {
         Self {
-            slots: HashMap::new(),
+            slots: Heap::new(),
             slot_max,
-            next_id: 0,
+            bytes: 0,
+            byte_max: usize::MAX,
+            roots: HashMap::new(),
             root: None,
+            nursery: None,
+            marking: None,
+            finalizable: Vec::new(),
+            finalizer_count: 0,
+            ephemerons: Heap::new(),
+            policy: Box::new(Threshold(usize::MAX)),
+            statistics: Statistics::default(),
+            hooks: Hooks::default(),
+            verify: false,
+            stagings: Vec::new(),
+            reserved_bytes: 0,
+            concurrent: None,
         }
     }
 
-    /// Replace the value of object at `address` with `value`. Return the original value of
-    /// managed object. If there's no object at `address` (maybe the object there has been
-    /// collected), throw `Error::InvalidAddress`.
-    pub fn replace(&mut self, address: &Address, value: T) -> Result<T, Error> {
-        let slot = self.slots.get_mut(address).ok_or(Error::InvalidAddress)?;
-        let content = mem::replace(&mut slot.content, value);
-        Ok(content)
-    }
-
-    /// Set object at `address` as root object. Only root object and objects kept by any
+    /// Add object at `address` into root set. Only root objects and objects kept by any
     /// object that has been considered as alive object in the current collecting pass
     /// will stay alive during garbage collection.
+    ///
+    /// Root set is reference counted, so an address added `n` times will stay in the set
+    /// until it is removed `n` times. If a collecting pass is in progress, the object is taken
+    /// as gray.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let first = collector.allocate(Leaf).unwrap();
+    /// let second = collector.allocate(Leaf).unwrap();
+    /// collector.add_root(first.clone());
+    /// collector.add_root(second.clone());
+    /// collector.add_root(second.clone());
+    /// collector.remove_root(&second);
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.alive_count(), 2);
+    /// collector.remove_root(&second);
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.alive_count(), 1);
+    /// ```
+    pub fn add_root(&mut self, address: Address) {
+        if let Some(marking) = &mut self.marking {
+            marking.stack.push(address.clone());
+        }
+        *self.roots.entry(address).or_insert(0) += 1;
+    }
+
+    /// Remove object at `address` from root set once. Return `false` if `address` is not in
+    /// root set.
+    pub fn remove_root(&mut self, address: &Address) -> bool {
+        match self.roots.get_mut(address) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.roots.remove(address);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Iterate over current root set. Each address is yielded once no matter how many times
+    /// it has been added.
+    pub fn roots(&self) -> impl Iterator<Item = &Address> {
+        self.roots.keys()
+    }
+
+    /// Set object at `address` as root object. The object set by previous calling (if any) is
+    /// removed from root set, and other roots added by `Collector::add_root` are not affected.
     pub fn set_root(&mut self, address: Address) {
+        if let Some(root) = self.root.take() {
+            self.remove_root(&root);
+        }
+        self.add_root(address.clone());
         self.root = Some(address);
     }
 
-    /// Return current root object. If no root object is set, return `None`, and every object
-    /// will be collected if a collecting pass is triggered.
+    /// Return current root object set by `Collector::set_root`. If no root object is set, return
+    /// `None`. Notice that objects in root set are still alive even if this method returns
+    /// `None`.
     pub fn root(&self) -> &Option<Address> {
         &self.root
     }
 
+    /// Return a reference to object at `address`. If there's no object at `address`, throw
+    /// `Error::InvalidAddress`.
+    pub fn get(&self, address: &Address) -> Result<&T, Error> {
+        let slot = self
+            .slots
+            .get(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        Ok(&slot.content)
+    }
+
     /// Return the total number of managed objects. Some of them may already be dead and will
     /// be collected in the following garbage collection.
     pub fn alive_count(&self) -> usize {
@@ -153,77 +329,195 @@ impl<T> Collector<T> {
 #[derive(Debug)]
 struct Slot<T> {
     mark: bool,
+    young: bool,
+    finalize: bool,
+    size: usize,
     content: T,
 }
 
 impl<T: Keep> Collector<T> {
-    /// Create a new managed object with `value`. If there's no available slot a garbage
-    /// collecting pass will be triggered. If there's still no available slot then
-    /// `Error::OutOfSlot` will be thrown. Any error thrown by collecting process
-    /// will be re-thrown.
+    /// Create a new managed object with `value`. If there's no available slot, or the object
+    /// does not fit in the byte budget, a garbage collecting pass will be triggered. If there's
+    /// still no available slot then `Error::OutOfSlots` will be thrown, and if the object still
+    /// does not fit then `Error::OutOfMemory` will be thrown. Any error thrown by collecting
+    /// process will be re-thrown. A `GcPolicy` could be set to trigger collecting pass in other
+    /// conditions.
+    ///
+    /// In generational mode a nursery collecting pass will be triggered first if the nursery
+    /// is full, unless a collecting pass started by `Collector::collect_step` is in progress.
     pub fn allocate(&mut self, value: T) -> Result<Address, Error> {
-        if self.slots.len() == self.slot_max {
+        let size = value.size();
+        if self.marking.is_none() && self.nursery.as_ref().is_some_and(Nursery::is_full) {
+            self.collect_nursery()?;
+        }
+        if self.should_collect(size) {
             self.collect()?;
         }
-        if self.slots.len() == self.slot_max {
-            return Err(Error::OutOfSlots);
+        if self.heap_status().alive_count >= self.slot_max {
+            let status = self.heap_status();
+            self.hooks.fire(&Event::OutOfSlots(&status));
+            return Err(Error::OutOfSlots {
+                alive_count: status.alive_count,
+                slot_max: status.slot_max,
+            });
+        }
+        if self.heap_status().bytes.saturating_add(size) > self.byte_max {
+            let status = self.heap_status();
+            self.hooks.fire(&Event::OutOfMemory(&status));
+            return Err(Error::OutOfMemory {
+                size,
+                bytes: status.bytes,
+                byte_max: status.byte_max,
+            });
+        }
+        self.bytes += size;
+        let address = self.slots.insert(Slot {
+            mark: self.marking.is_some(),
+            young: self.nursery.is_some(),
+            finalize: false,
+            size,
+            content: value,
+        });
+        if let Some(nursery) = &mut self.nursery {
+            nursery.young.push(address.clone());
         }
-        let address = Address(self.next_id);
-        self.next_id += 1;
-        self.slots.insert(
-            address.clone(),
-            Slot {
-                mark: false,
-                content: value,
-            },
-        );
+        self.touch(&address);
         Ok(address)
     }
 
-    /// Clean up all dead objects, which are unreachable from root object, or all objects
-    /// if the root object is not set. If root object address is invalid, or any alive object
-    /// keeps an object at invalid address, then `Memory::InvalidAddress` will be thrown.
+    /// Replace the value of object at `address` with `value`. Return the original value of
+    /// managed object. If there's no object at `address` (maybe the object there has been
+    /// collected), throw `Error::InvalidAddress`.
+    pub fn replace(&mut self, address: &Address, value: T) -> Result<T, Error> {
+        let content = mem::replace(self.get_mut(address)?, value);
+        self.resize(address);
+        Ok(content)
+    }
+
+    /// Return a mutable reference to object at `address`. If there's no object at `address`,
+    /// throw `Error::InvalidAddress`.
     ///
-    /// This method will be invoked if `Collector::allocate` is called but no slot is available,
-    /// but it could also be explicit called by user. Statistics log will be printed after
-    /// each collecting pass.
-    pub fn collect(&mut self) -> Result<(), Error> {
-        let start = Instant::now();
-
-        let mut stack = Vec::new();
-        if let Some(address) = &self.root {
-            stack.push(address.to_owned());
+    /// The object is considered as modified once this method is called, so write barriers
+    /// of generational mode and incremental marking are applied to it, no matter whether it
+    /// is actually modified or not. Use `Collector::get` for reading. Size change of the object
+    /// is not accounted until the next collecting pass, use `Collector::update` instead if it
+    /// matters.
+    pub fn get_mut(&mut self, address: &Address) -> Result<&mut T, Error> {
+        self.barrier(address);
+        let slot = self
+            .slots
+            .get_mut(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        if let Some(nursery) = &mut self.nursery {
+            if !slot.young {
+                nursery.remembered.insert(address.to_owned());
+            }
+        }
+        Ok(&mut slot.content)
+    }
+
+    /// Update object at `address` in place by calling `f` with it, and return what `f`
+    /// returns. If there's no object at `address`, throw `Error::InvalidAddress`.
+    pub fn update<R, F: FnOnce(&mut T) -> R>(
+        &mut self,
+        address: &Address,
+        f: F,
+    ) -> Result<R, Error> {
+        let result = f(self.get_mut(address)?);
+        self.resize(address);
+        Ok(result)
+    }
+
+    /// Recompute the size of object at `address` and update the byte total.
+    fn resize(&mut self, address: &Address) {
+        if let Some(slot) = self.slots.get_mut(address) {
+            let size = slot.content.size();
+            self.bytes = self.bytes - slot.size + size;
+            slot.size = size;
         }
-        while let Some(address) = stack.pop() {
-            let slot = self.slots.get_mut(&address).ok_or(Error::InvalidAddress)?;
-            if slot.mark {
-                continue;
+    }
+
+    /// Clean up unmarked objects, and return the number of them. Sizes of surviving objects are
+    /// recomputed.
+    fn sweep(&mut self) -> usize {
+        let alive_count = self.slots.len();
+        let hooks = &mut self.hooks;
+        let concurrent = &mut self.concurrent;
+        let mut bytes = 0;
+        self.slots.retain(|address, slot| {
+            let alive = slot.mark;
+            slot.mark = false;
+            slot.young = false;
+            if alive {
+                slot.size = slot.content.size();
+                bytes += slot.size;
+            } else {
+                hooks.fire(&Event::Swept(address));
+                if let Some(concurrent) = concurrent {
+                    concurrent.dirty.insert(address.to_owned());
+                }
             }
-            slot.mark = true;
-            slot.content.with_keep(|address| {
-                stack.push(address.to_owned());
-            });
+            alive
+        });
+        self.bytes = bytes;
+        self.clean_ephemerons();
+        if let Some(nursery) = &mut self.nursery {
+            nursery.young.clear();
+            nursery.remembered.clear();
         }
-        let mut alive_slots = HashMap::new();
-        for (address, slot) in mem::replace(&mut self.slots, HashMap::new()).into_iter() {
-            if slot.mark {
-                alive_slots.insert(
-                    address,
-                    Slot {
-                        mark: false,
-                        content: slot.content,
-                    },
-                );
+        alive_count - self.slots.len()
+    }
+
+    /// Clean up all dead objects, which are unreachable from any object in root set or
+    /// finalization queue, or all objects if both of them are empty. Dead objects with
+    /// finalizer are put into finalization queue instead. Values in ephemeron tables are alive
+    /// only if their keys are alive, and entries with dead keys are removed. If any root
+    /// address is invalid, or any alive object keeps an object at invalid address, then
+    /// `Error::InvalidRoot` or `Error::DanglingEdge` will be thrown, the collecting pass is
+    /// aborted and the collector is left as it was before collecting. Use `Collector::verify`
+    /// to find out the invalid addresses.
+    ///
+    /// This method will be invoked if `Collector::allocate` is called but no slot is available,
+    /// but it could also be explicit called by user. Statistics of the collecting pass is
+    /// returned, and statistics log will be printed after each collecting pass as well. In
+    /// generational mode all surviving objects are promoted.
+    ///
+    /// If a collecting pass started by `Collector::collect_step` is in progress, it is finished
+    /// first, and then a complete collecting pass is performed, so objects dead at the time of
+    /// calling will all be cleaned up.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Error, Keep};
+    /// # struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let stale = collector.allocate(ListNode(None)).unwrap();
+    /// collector.collect().unwrap();
+    /// let node = collector.allocate(ListNode(Some(stale.clone()))).unwrap();
+    /// collector.register_finalizer(&node).unwrap();
+    /// match collector.collect() {
+    ///     Err(Error::DanglingEdge { from, to }) => assert_eq!((from, to), (node.clone(), stale)),
+    ///     _ => unreachable!(),
+    /// }
+    /// // the object is not queued, and its finalizer is still registered
+    /// assert!(collector.drain_finalizable().is_empty());
+    /// assert!(collector.unregister_finalizer(&node).unwrap());
+    /// ```
+    pub fn collect(&mut self) -> Result<CollectStats, Error> {
+        let mut remaining = if self.marking.is_some() { 2 } else { 1 };
+        loop {
+            if let Some(stats) = self.collect_step(usize::MAX)? {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Ok(stats);
+                }
             }
         }
-        self.slots = alive_slots;
-
-        info!(
-            target: "hulunbuir",
-            "garbage collected in {} ms, {:.2}% of available slots used",
-            start.elapsed().as_micros() as f32 / 1000.0,
-            self.slots.len() as f32 / self.slot_max as f32 * 100.0
-        );
-        Ok(())
     }
 }
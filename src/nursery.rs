@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::{
+    error::Error,
+    hook::Event,
+    incremental::trace,
+    stats::{CollectKind, CollectStats},
+    Address, Collector, Keep,
+};
+
+/// Young generation bookkeeping of a generational collector.
+#[derive(Debug)]
+pub(crate) struct Nursery {
+    pub(crate) max: usize,
+    pub(crate) young: Vec<Address>,
+    pub(crate) remembered: HashSet<Address>,
+}
+
+impl Nursery {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            max,
+            young: Vec::new(),
+            remembered: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.young.len() >= self.max
+    }
+}
+
+impl<T> Collector<T> {
+    /// Enable generational mode with a nursery of `nursery_max` slots, or disable it with `None`.
+    ///
+    /// In generational mode objects created by `Collector::allocate` are young, and a nursery
+    /// collecting pass is triggered whenever `nursery_max` young objects have been allocated.
+    /// Objects surviving any collecting pass are promoted to old generation, and they will only
+    /// be collected by full collecting passes, i.e. `Collector::collect`. Every old object that
+    /// is replaced (including taking and filling a `Slot`) is recorded in a remembered set, so
+    /// the young objects it keeps are not collected by nursery passes.
+    ///
+    /// All objects already in the collector are considered old when switching mode.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    ///
+    /// let mut collector = Collector::new(128);
+    /// collector.set_nursery(Some(16));
+    /// let root = collector.allocate(ListNode(None)).unwrap();
+    /// collector.set_root(root.clone());
+    /// collector.collect_nursery().unwrap();
+    /// assert_eq!(collector.young_count(), 0);
+    ///
+    /// // root is old now, and replacing it records it in remembered set
+    /// let tail = collector.allocate(ListNode(None)).unwrap();
+    /// collector.replace(&root, ListNode(Some(tail))).unwrap();
+    /// let _orphan = collector.allocate(ListNode(None)).unwrap();
+    /// assert_eq!(collector.young_count(), 2);
+    /// collector.collect_nursery().unwrap();
+    /// assert_eq!(collector.young_count(), 0);
+    /// assert_eq!(collector.alive_count(), 2);
+    /// ```
+    pub fn set_nursery(&mut self, nursery_max: Option<usize>) {
+        if let Some(nursery) = self.nursery.take() {
+            for address in nursery.young {
+                if let Some(slot) = self.slots.get_mut(&address) {
+                    slot.young = false;
+                }
+            }
+        }
+        self.nursery = nursery_max.map(Nursery::new);
+    }
+
+    /// Return the number of young objects, which is always 0 if generational mode is disabled.
+    pub fn young_count(&self) -> usize {
+        self.nursery
+            .as_ref()
+            .map(|nursery| nursery.young.len())
+            .unwrap_or(0)
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Clean up dead young objects and promote the survivors. Dead young objects with finalizer
+    /// are put into finalization queue instead. Old objects are treated as
+    /// alive, so the objects kept by them are found through the remembered set instead of
+    /// tracing the whole heap. Values in ephemeron tables with old keys are treated as alive as
+    /// well. Do nothing if generational mode is disabled.
+    ///
+    /// This method will be invoked by `Collector::allocate` when the nursery is full, but it
+    /// could also be explicit called by user. `Error::InvalidRoot` or `Error::DanglingEdge` will
+    /// be thrown in the same situation as `Collector::collect`.
+    ///
+    /// If a collecting pass started by `Collector::collect_step` is in progress, it is finished
+    /// instead, which cleans up young objects as well.
+    ///
+    /// Return the statistics of the collecting pass, or `None` if generational mode is disabled.
+    /// If the collecting pass is aborted, the collector is left as it was before collecting.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// collector.set_nursery(Some(8));
+    /// let stale = collector.allocate(ListNode(None)).unwrap();
+    /// collector.collect_nursery().unwrap();
+    /// let node = collector.allocate(ListNode(Some(stale))).unwrap();
+    /// collector.register_finalizer(&node).unwrap();
+    /// assert!(collector.collect_nursery().is_err());
+    /// assert!(collector.drain_finalizable().is_empty());
+    /// assert!(collector.unregister_finalizer(&node).unwrap());
+    /// assert_eq!(collector.young_count(), 1);
+    /// ```
+    pub fn collect_nursery(&mut self) -> Result<Option<CollectStats>, Error> {
+        if self.marking.is_some() {
+            loop {
+                if let Some(stats) = self.collect_step(usize::MAX)? {
+                    return Ok(Some(stats));
+                }
+            }
+        }
+        if self.nursery.is_none() {
+            return Ok(None);
+        }
+        self.publish();
+        self.debug_verify("before");
+        self.hooks.fire(&Event::BeforeCollect(CollectKind::Nursery));
+        let start = Instant::now();
+
+        let mut finalized = Vec::new();
+        let marked = match self.mark_nursery(&mut finalized) {
+            Ok(marked) => marked,
+            Err(error) => {
+                self.restore_finalizers(&finalized);
+                for address in &self.nursery.as_ref().unwrap().young {
+                    self.slots.get_mut(address).unwrap().mark = false;
+                }
+                return Err(error);
+            }
+        };
+        self.finalizable.extend(finalized);
+        let sweep_start = Instant::now();
+        let nursery = self.nursery.as_mut().unwrap();
+        let mut swept = 0;
+        for address in nursery.young.drain(..) {
+            let slot = self.slots.get_mut(&address).unwrap();
+            if slot.mark {
+                slot.mark = false;
+                slot.young = false;
+                let size = slot.content.size();
+                self.bytes = self.bytes - slot.size + size;
+                slot.size = size;
+            } else {
+                self.bytes -= slot.size;
+                self.slots.remove(&address);
+                self.hooks.fire(&Event::Swept(&address));
+                if let Some(concurrent) = &mut self.concurrent {
+                    concurrent.dirty.insert(address.to_owned());
+                }
+                swept += 1;
+            }
+        }
+        nursery.remembered.clear();
+        self.clean_ephemerons();
+        self.debug_verify("after");
+        self.after_collect();
+
+        let stats = CollectStats {
+            kind: CollectKind::Nursery,
+            marked,
+            swept,
+            surviving: self.slots.len(),
+            mark_duration: sweep_start - start,
+            sweep_duration: sweep_start.elapsed(),
+            step_count: 1,
+            max_pause: start.elapsed(),
+            slot_max: self.slot_max,
+            bytes: self.bytes,
+        };
+        self.record(&stats);
+        Ok(Some(stats))
+    }
+
+    /// Mark young objects reachable from roots, finalization queue, remembered set and
+    /// ephemeron values with old keys, and return the number of them. Young objects with
+    /// finalizer found dead are pushed into `finalized`.
+    fn mark_nursery(&mut self, finalized: &mut Vec<Address>) -> Result<usize, Error> {
+        let nursery = self.nursery.as_ref().unwrap();
+        let mut stack: Vec<_> = self.trace_roots().cloned().collect();
+        for address in &nursery.remembered {
+            let slot = match self.slots.get(address) {
+                Some(slot) => slot,
+                None => return Err(self.invalid_reference(address.to_owned())),
+            };
+            slot.content.with_keep(|address| {
+                stack.push(address.to_owned());
+            });
+        }
+        for (_, entries) in self.ephemerons.iter() {
+            for (key, value) in entries {
+                match self.slots.get(key) {
+                    Some(slot) if !slot.young => stack.push(value.to_owned()),
+                    Some(_) => {}
+                    None => return Err(self.invalid_reference(key.to_owned())),
+                }
+            }
+        }
+        let mut marked = 0;
+        loop {
+            while let Some(address) = stack.pop() {
+                let slot = match self.slots.get_mut(&address) {
+                    Some(slot) => slot,
+                    None => return Err(self.invalid_reference(address)),
+                };
+                if !slot.young || slot.mark {
+                    continue;
+                }
+                slot.mark = true;
+                marked += 1;
+                trace(&address, &slot.content, &self.ephemerons, |address| {
+                    stack.push(address.to_owned());
+                });
+            }
+            let queued = self.find_finalizable(true);
+            if queued.is_empty() {
+                break;
+            }
+            finalized.extend(queued.iter().cloned());
+            stack.extend(queued);
+        }
+        Ok(marked)
+    }
+}
@@ -0,0 +1,225 @@
+//!
+//! By default, `Collector::allocate` triggers a collecting pass only when the heap is full, i.e.
+//! all slots are used or the byte budget is exceeded.
+//! A `GcPolicy` could be set to a collector with `Collector::set_policy` to decide when to
+//! collect instead. It is consulted on every allocation, and is noticed after every collecting
+//! pass, when it could also grow or shrink the heap by changing `slot_max`.
+//!
+//! ```rust
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::policy::{GcPolicy, HeapStatus};
+//!
+//! struct Leaf;
+//!
+//! impl Keep for Leaf {
+//!     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+//! }
+//!
+//! // collect when heap is full, and double the heap if more than half of it survives
+//! #[derive(Debug)]
+//! struct Doubling;
+//!
+//! impl GcPolicy for Doubling {
+//!     fn should_collect(&mut self, status: &HeapStatus) -> bool {
+//!         status.is_full()
+//!     }
+//!
+//!     fn after_collect(&mut self, status: &HeapStatus) -> Option<usize> {
+//!         if status.alive_count * 2 > status.slot_max {
+//!             Some(status.slot_max * 2)
+//!         } else {
+//!             None
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut collector = Collector::new(4);
+//!     collector.set_policy(Doubling);
+//!     let root = collector.allocate(Leaf).unwrap();
+//!     collector.add_root(root);
+//!     for _ in 0..4 {
+//!         let leaf = collector.allocate(Leaf).unwrap();
+//!         collector.add_root(leaf);
+//!     }
+//!     assert_eq!(collector.slot_max(), 8);
+//! }
+//! ```
+
+use std::fmt::Debug;
+
+use crate::Collector;
+
+/// Status of heap passed to `GcPolicy`.
+#[derive(Debug, Clone)]
+pub struct HeapStatus {
+    /// Number of managed objects, including dead ones not collected yet and slots reserved by
+    /// allocation buffers.
+    pub alive_count: usize,
+    /// Current maximum number of managed objects.
+    pub slot_max: usize,
+    /// Total size of managed objects in bytes, including byte allowances reserved by allocation
+    /// buffers. When consulted on allocation, the size of the object being allocated is
+    /// included.
+    pub bytes: usize,
+    /// Current byte budget, which is `usize::MAX` if it is not set.
+    pub byte_max: usize,
+}
+
+impl HeapStatus {
+    /// Return `true` if all slots are used, or the total size exceeds the byte budget.
+    pub fn is_full(&self) -> bool {
+        self.alive_count >= self.slot_max || self.bytes > self.byte_max
+    }
+}
+
+/// Strategy of when to collect and how large the heap is.
+pub trait GcPolicy: Debug + Send {
+    /// Called by `Collector::allocate` before creating every object. Return `true` to trigger
+    /// a collecting pass.
+    ///
+    /// The collector never creates more than `slot_max` objects no matter what this method
+    /// returns. If the heap is full and this method returns `false`, `Error::OutOfSlots` will
+    /// be thrown.
+    fn should_collect(&mut self, status: &HeapStatus) -> bool;
+
+    /// Called after every finished collecting pass, including nursery collecting pass. Return
+    /// a new `slot_max` to resize the heap, which will not be smaller than the number of
+    /// alive objects. Return `None` to keep current size, which is the default behavior.
+    fn after_collect(&mut self, _status: &HeapStatus) -> Option<usize> {
+        None
+    }
+}
+
+/// Collect when the number of objects reaches the threshold, or the heap is full.
+///
+/// `Threshold(usize::MAX)`, which collects only when the heap is full, is the default policy
+/// of collectors.
+#[derive(Debug, Clone)]
+pub struct Threshold(pub usize);
+
+impl GcPolicy for Threshold {
+    fn should_collect(&mut self, status: &HeapStatus) -> bool {
+        status.alive_count >= self.0 || status.is_full()
+    }
+}
+
+/// Collect when the number of objects grows by `factor` times since the last collecting pass,
+/// or the heap is full. For example, `Growth::new(2.0, 64)` collects when the heap doubles,
+/// and never collects before there are 64 objects.
+#[derive(Debug, Clone)]
+pub struct Growth {
+    factor: f64,
+    min: usize,
+    threshold: usize,
+}
+
+impl Growth {
+    /// Create a growth policy with `factor`, and the minimum threshold `min`.
+    pub fn new(factor: f64, min: usize) -> Self {
+        Self {
+            factor,
+            min,
+            threshold: min,
+        }
+    }
+}
+
+impl GcPolicy for Growth {
+    fn should_collect(&mut self, status: &HeapStatus) -> bool {
+        status.alive_count >= self.threshold || status.is_full()
+    }
+
+    fn after_collect(&mut self, status: &HeapStatus) -> Option<usize> {
+        self.threshold = ((status.alive_count as f64 * self.factor) as usize).max(self.min);
+        None
+    }
+}
+
+/// Never collect automatically. Collecting passes are only performed when requested by user.
+#[derive(Debug, Clone)]
+pub struct Never;
+
+impl GcPolicy for Never {
+    fn should_collect(&mut self, _status: &HeapStatus) -> bool {
+        false
+    }
+}
+
+impl<T> Collector<T> {
+    /// Set the policy that decides when to collect. See module `policy` for details.
+    pub fn set_policy<P: GcPolicy + 'static>(&mut self, policy: P) {
+        self.policy = Box::new(policy);
+    }
+
+    /// Return current maximum number of managed objects.
+    pub fn slot_max(&self) -> usize {
+        self.slot_max
+    }
+
+    /// Set the budget of total size of objects in bytes, which is unlimited by default. Sizes of
+    /// objects are reported by `Keep::size`. Setting a budget lower than current total size does
+    /// not clean up anything by itself.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Error, Keep};
+    /// struct Buffer(Vec<u8>);
+    ///
+    /// impl Keep for Buffer {
+    ///     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    ///
+    ///     fn size(&self) -> usize {
+    ///         std::mem::size_of::<Self>() + self.0.capacity()
+    ///     }
+    /// }
+    ///
+    /// let mut collector = Collector::new(128);
+    /// collector.set_byte_max(4096);
+    /// let buffer = collector.allocate(Buffer(vec![0; 3000])).unwrap();
+    /// collector.set_root(buffer);
+    /// match collector.allocate(Buffer(vec![0; 3000])) {
+    ///     Err(Error::OutOfMemory { .. }) => {}
+    ///     _ => unreachable!(),
+    /// }
+    /// assert!(collector.bytes() > 3000);
+    /// ```
+    pub fn set_byte_max(&mut self, byte_max: usize) {
+        self.byte_max = byte_max;
+    }
+
+    /// Return current byte budget, which is `usize::MAX` if it is not set.
+    pub fn byte_max(&self) -> usize {
+        self.byte_max
+    }
+
+    /// Return the total size of managed objects in bytes. Size change of objects modified
+    /// through `Collector::get_mut` is not counted until the next collecting pass.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Return current status of heap.
+    pub fn heap_status(&self) -> HeapStatus {
+        HeapStatus {
+            alive_count: self.slots.len() + self.slots.reserved(),
+            slot_max: self.slot_max,
+            bytes: self.bytes + self.reserved_bytes,
+            byte_max: self.byte_max,
+        }
+    }
+
+    /// Consult policy whether to collect before allocating an object of `size` bytes.
+    pub(crate) fn should_collect(&mut self, size: usize) -> bool {
+        let mut status = self.heap_status();
+        status.bytes = status.bytes.saturating_add(size);
+        self.policy.should_collect(&status)
+    }
+
+    /// Notice policy that a collecting pass is finished, and resize heap if required.
+    pub(crate) fn after_collect(&mut self) {
+        let status = self.heap_status();
+        if let Some(slot_max) = self.policy.after_collect(&status) {
+            self.slot_max = slot_max.max(status.alive_count);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{error::Error, incremental::trace, Address, Collector, Keep};
+
+impl<T: Keep> Collector<T> {
+    /// Return the shortest chain of addresses through which object at `address` is reachable,
+    /// starting with an address in root set, finalization queue or an unflushed allocation
+    /// buffer, and ending with `address`, or `None` if the object is dead. Edges are the same as
+    /// the ones traced by `Collector::collect`, so a value in ephemeron table is reachable from
+    /// its key. Invalid addresses are skipped. If there's no object at `address`, throw
+    /// `Error::InvalidAddress`.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let tail = collector.allocate(ListNode(None)).unwrap();
+    /// let middle = collector.allocate(ListNode(Some(tail.clone()))).unwrap();
+    /// let head = collector.allocate(ListNode(Some(middle.clone()))).unwrap();
+    /// collector.set_root(head.clone());
+    /// collector.add_root(middle.clone());
+    /// let orphan = collector.allocate(ListNode(Some(tail.clone()))).unwrap();
+    /// let path = collector.retaining_path(&tail).unwrap();
+    /// assert_eq!(path, Some(vec![middle.clone(), tail.clone()]));
+    /// assert_eq!(collector.retaining_path(&orphan).unwrap(), None);
+    /// assert_eq!(collector.retainers(&tail).unwrap(), vec![middle, orphan]);
+    /// ```
+    pub fn retaining_path(&self, address: &Address) -> Result<Option<Vec<Address>>, Error> {
+        if self.slots.get(address).is_none() {
+            return Err(Error::InvalidAddress(address.to_owned()));
+        }
+        let mut parents: HashMap<Address, Option<Address>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in self.trace_roots() {
+            if self.slots.get(root).is_some() && !parents.contains_key(root) {
+                parents.insert(root.to_owned(), None);
+                queue.push_back(root.to_owned());
+            }
+        }
+        while let Some(current) = queue.pop_front() {
+            if &current == address {
+                let mut path = vec![current];
+                while let Some(Some(parent)) = parents.get(path.last().unwrap()) {
+                    path.push(parent.clone());
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            let slot = self.slots.get(&current).unwrap();
+            trace(&current, &slot.content, &self.ephemerons, |child| {
+                if self.slots.get(child).is_some() && !parents.contains_key(child) {
+                    parents.insert(child.to_owned(), Some(current.clone()));
+                    queue.push_back(child.to_owned());
+                }
+            });
+        }
+        Ok(None)
+    }
+
+    /// Return the addresses of objects that directly keep object at `address`, including the
+    /// keys it is associated with in ephemeron tables, in the order of address index. Dead
+    /// objects that have not been cleaned up are included as well, and whether the object is
+    /// in root set is not considered. If there's no object at `address`, throw
+    /// `Error::InvalidAddress`.
+    pub fn retainers(&self, address: &Address) -> Result<Vec<Address>, Error> {
+        if self.slots.get(address).is_none() {
+            return Err(Error::InvalidAddress(address.to_owned()));
+        }
+        let mut retainers = Vec::new();
+        for (referrer, slot) in self.slots.iter() {
+            let mut keeping = false;
+            trace(&referrer, &slot.content, &self.ephemerons, |child| {
+                keeping |= child == address;
+            });
+            if keeping {
+                retainers.push(referrer);
+            }
+        }
+        Ok(retainers)
+    }
+}
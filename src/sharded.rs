@@ -0,0 +1,433 @@
+//!
+//! Sharing `Collector<Slot<T>>` between threads as `Arc<Mutex<Collector<Slot<T>>>>` makes every
+//! `take`, `fill` and `allocate` contend on the same lock. `ShardedCollector<T>` splits the
+//! slots into several shards, each of which is protected by its own lock, and addresses are
+//! assigned to shards by their indices. So the methods working on objects in different shards
+//! never contend, and all of them take `&self`, so a collector could be shared with plain
+//! `Arc<ShardedCollector<T>>`.
+//!
+//! Only collecting pass needs coordination: it locks root set and then every shard in order,
+//! marks and sweeps the whole heap, and releases them, so other threads are stopped only when
+//! they touch the heap during the collecting pass.
+//!
+//! The same caveat of `Slot` applies here: collector will not be aware of any change to the
+//! kept list of taken object until it is filled back. Besides, a newly allocated object is
+//! dead until it is kept by an alive object or added into root set. Since there's no global
+//! lock to hold through all the stages, hold a `Pause` returned by `ShardedCollector::pause`
+//! instead, which prevents any collecting pass from starting until it is dropped.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use std::thread;
+//! use hulunbuir::{Address, Keep};
+//! use hulunbuir::sharded::ShardedCollector;
+//! use hulunbuir::slot::Take;
+//!
+//! struct ListNode(i32, Option<Address>);
+//!
+//! impl Keep for ListNode {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         if let Some(tail) = &self.1 {
+//!             keep(tail)
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let collector = Arc::new(ShardedCollector::new(4, 128));
+//!     let root = collector.allocate(ListNode(0, None)).unwrap();
+//!     collector.add_root(root.clone());
+//!
+//!     let handles: Vec<_> = (1..=4)
+//!         .map(|i| {
+//!             let collector = Arc::clone(&collector);
+//!             let root = root.clone();
+//!             thread::spawn(move || {
+//!                 let _pause = collector.pause();
+//!                 let mut node = loop {
+//!                     match collector.take(&root).unwrap() {
+//!                         Take::Free(node) => break node,
+//!                         Take::Busy(parker) => parker.park(),
+//!                     }
+//!                 };
+//!                 let tail = collector.allocate(ListNode(i, node.1.take())).unwrap();
+//!                 node.1 = Some(tail);
+//!                 collector.fill(&root, node).unwrap();
+//!             })
+//!         })
+//!         .collect();
+//!     for handle in handles {
+//!         handle.join().unwrap();
+//!     }
+//!     let _orphan = collector.allocate(ListNode(5, None)).unwrap();
+//!     collector.collect().unwrap();
+//!     assert_eq!(collector.alive_count(), 5);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use crate::{
+    error::Error,
+    heap::Heap,
+    slot::{Slot, Take},
+    stats::{CollectKind, CollectStats},
+    Address, Keep,
+};
+
+struct Object<T> {
+    mark: bool,
+    slot: Slot<T>,
+}
+
+type Shard<T> = Heap<Object<T>>;
+
+/// Thread-safe collector with sharded slot storage. See module level document for details.
+pub struct ShardedCollector<T> {
+    shards: Vec<Mutex<Shard<T>>>,
+    slot_max: usize,
+    roots: Mutex<HashMap<Address, usize>>,
+    next_shard: AtomicUsize,
+    collecting: RwLock<()>,
+    /// Number of alive `Pause` held by each thread.
+    pauses: Mutex<HashMap<ThreadId, usize>>,
+    /// Whether an allocation failed while paused, so a collecting pass should be performed
+    /// once the last `Pause` is dropped.
+    requested: AtomicBool,
+}
+
+/// Guard returned by `ShardedCollector::pause`. No collecting pass could start while it is
+/// alive.
+pub struct Pause<'a, T: Keep> {
+    collector: &'a ShardedCollector<T>,
+    guard: Option<RwLockReadGuard<'a, ()>>,
+}
+
+impl<T> ShardedCollector<T> {
+    /// Create a collector with `shard_count` shards and `slot_max` slots in total, which are
+    /// evenly split into shards. If `slot_max` is not divisible by `shard_count`, the first
+    /// `slot_max % shard_count` shards have one more slot than the others.
+    pub fn new(shard_count: usize, slot_max: usize) -> Self {
+        assert!(shard_count > 0, "shard count must be positive");
+        Self {
+            shards: (0..shard_count).map(|_| Mutex::new(Heap::new())).collect(),
+            slot_max,
+            roots: Mutex::new(HashMap::new()),
+            next_shard: AtomicUsize::new(0),
+            collecting: RwLock::new(()),
+            pauses: Mutex::new(HashMap::new()),
+            requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Add object at `address` into root set. See `Collector::add_root` for details.
+    pub fn add_root(&self, address: Address) {
+        *self.roots.lock().unwrap().entry(address).or_insert(0) += 1;
+    }
+
+    /// Remove object at `address` from root set once. Return `false` if `address` is not in
+    /// root set.
+    pub fn remove_root(&self, address: &Address) -> bool {
+        let mut roots = self.roots.lock().unwrap();
+        match roots.get_mut(address) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    roots.remove(address);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the total number of managed objects, including dead ones not collected yet.
+    pub fn alive_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Return the maximum number of managed objects.
+    pub fn slot_max(&self) -> usize {
+        self.slot_max
+    }
+
+    /// Return the maximum number of objects in the shard at `index`.
+    fn shard_max(&self, index: usize) -> usize {
+        let count = self.shards.len();
+        self.slot_max / count + usize::from(index < self.slot_max % count)
+    }
+
+    /// Split global `address` into the index of its shard and the address inside the shard.
+    fn locate(&self, address: &Address) -> (usize, Address) {
+        let count = self.shards.len();
+        let local = Address {
+            index: address.index / count,
+            generation: address.generation,
+        };
+        (address.index % count, local)
+    }
+
+    fn globalize(&self, shard: usize, local: Address) -> Address {
+        Address {
+            index: local.index * self.shards.len() + shard,
+            generation: local.generation,
+        }
+    }
+
+    /// Lock the shard of `address` and call `f` with the object there. If there's no object
+    /// at `address`, throw `Error::InvalidAddress`.
+    fn with_slot<R, F: FnOnce(&mut Slot<T>) -> R>(
+        &self,
+        address: &Address,
+        f: F,
+    ) -> Result<R, Error> {
+        let (shard, local) = self.locate(address);
+        let mut shard = self.shards[shard].lock().unwrap();
+        let object = shard
+            .get_mut(&local)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        Ok(f(&mut object.slot))
+    }
+}
+
+impl<T: Keep> ShardedCollector<T> {
+    /// Prevent collecting passes from starting until the returned guard is dropped. A
+    /// collecting pass in progress is waited for.
+    ///
+    /// Calling `ShardedCollector::collect` while holding a `Pause` in the same thread is a dead
+    /// lock. See `ShardedCollector::allocate` for allocating while paused.
+    pub fn pause(&self) -> Pause<'_, T> {
+        let guard = self.collecting.read().unwrap();
+        *self
+            .pauses
+            .lock()
+            .unwrap()
+            .entry(thread::current().id())
+            .or_insert(0) += 1;
+        Pause {
+            collector: self,
+            guard: Some(guard),
+        }
+    }
+
+    /// Create a new managed object with `value`. Shards are chosen in turn, and the following
+    /// ones are tried if the chosen shard is full.
+    ///
+    /// If all shards are full, current thread waits until the collecting pass in progress
+    /// and every `Pause` held by other threads are finished, and triggers a collecting pass
+    /// if there's still no available slot. `Error::OutOfSlots` is thrown if there's no
+    /// available slot after collecting. So allocating blocks as long as another thread holds
+    /// a `Pause`, and it is a dead lock if that thread waits for current one meanwhile.
+    ///
+    /// If current thread holds a `Pause` itself, no collecting pass could be performed without
+    /// collecting its newly allocated objects, so `Error::OutOfSlots` is thrown immediately,
+    /// and a collecting pass is performed when the last `Pause` is dropped, so allocating
+    /// could be retried after that.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Error, Keep};
+    /// # use hulunbuir::sharded::ShardedCollector;
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let collector = ShardedCollector::new(2, 2);
+    /// let pause = collector.pause();
+    /// collector.allocate(Leaf).unwrap();
+    /// collector.allocate(Leaf).unwrap();
+    /// match collector.allocate(Leaf) {
+    ///     Err(Error::OutOfSlots { .. }) => {}
+    ///     _ => unreachable!(),
+    /// }
+    /// // the requested collecting pass is performed here
+    /// drop(pause);
+    /// assert_eq!(collector.alive_count(), 0);
+    /// ```
+    pub fn allocate(&self, value: T) -> Result<Address, Error> {
+        let object = Object {
+            mark: false,
+            slot: Slot::new(value),
+        };
+        let object = match self.try_allocate(object) {
+            Ok(address) => return Ok(address),
+            Err(object) => object,
+        };
+        let paused = self
+            .pauses
+            .lock()
+            .unwrap()
+            .contains_key(&thread::current().id());
+        if paused {
+            self.requested.store(true, Ordering::SeqCst);
+            return Err(self.out_of_slots());
+        }
+        let guard = self.collecting.write().unwrap();
+        // slots may have been freed by the collecting pass waited for
+        let object = match self.try_allocate(object) {
+            Ok(address) => return Ok(address),
+            Err(object) => object,
+        };
+        self.collect_locked(guard)?;
+        self.try_allocate(object).map_err(|_| self.out_of_slots())
+    }
+
+    fn out_of_slots(&self) -> Error {
+        Error::OutOfSlots {
+            alive_count: self.alive_count(),
+            slot_max: self.slot_max(),
+        }
+    }
+
+    fn try_allocate(&self, object: Object<T>) -> Result<Address, Object<T>> {
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.shards.len() {
+            let index = (start + offset) % self.shards.len();
+            let mut shard = self.shards[index].lock().unwrap();
+            if shard.len() < self.shard_max(index) {
+                let local = shard.insert(object);
+                return Ok(self.globalize(index, local));
+            }
+        }
+        Err(object)
+    }
+
+    /// Take the object at `address` out and leave a hole there. Only the shard of `address` is
+    /// locked. See `Collector::take` for details.
+    pub fn take(&self, address: &Address) -> Result<Take<T>, Error> {
+        self.with_slot(address, Slot::take_value)
+    }
+
+    /// Fill the hole at `address` with `value`. Only the shard of `address` is locked. See
+    /// `Collector::fill` for details.
+    pub fn fill(&self, address: &Address, value: T) -> Result<(), Error> {
+        self.with_slot(address, |slot| slot.fill_value(value))?
+            .map_err(|_| Error::DuplicatedFilling(address.to_owned()))
+    }
+
+    /// Clean up all dead objects. Root set and all shards are locked during the collecting
+    /// pass, and it waits until all `Pause` are dropped. If any address is invalid, throw
+    /// `Error::InvalidRoot` or `Error::DanglingEdge` and leave the heap untouched. See
+    /// `Collector::collect` for details.
+    pub fn collect(&self) -> Result<CollectStats, Error> {
+        self.collect_locked(self.collecting.write().unwrap())
+    }
+
+    fn collect_locked<G>(&self, _guard: G) -> Result<CollectStats, Error> {
+        let start = Instant::now();
+        let roots = self.roots.lock().unwrap();
+        let mut shards: Vec<MutexGuard<Shard<T>>> = self
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap())
+            .collect();
+
+        let mut stack: Vec<_> = roots
+            .keys()
+            .map(|address| (None, address.to_owned()))
+            .collect();
+        let mut marked = 0;
+        while let Some((referrer, address)) = stack.pop() {
+            let (shard, local) = self.locate(&address);
+            let object = match shards[shard].get_mut(&local) {
+                Some(object) => object,
+                None => {
+                    for shard in &mut shards {
+                        for (_, object) in shard.iter_mut() {
+                            object.mark = false;
+                        }
+                    }
+                    return Err(match referrer {
+                        Some(from) => Error::DanglingEdge { from, to: address },
+                        None => Error::InvalidRoot(address),
+                    });
+                }
+            };
+            if object.mark {
+                continue;
+            }
+            object.mark = true;
+            marked += 1;
+            object.slot.with_keep(|child| {
+                stack.push((Some(address.clone()), child.to_owned()));
+            });
+        }
+
+        let sweep_start = Instant::now();
+        let mut swept = 0;
+        let mut surviving = 0;
+        let mut bytes = 0;
+        for shard in &mut shards {
+            let count = shard.len();
+            shard.retain(|_, object| {
+                let alive = object.mark;
+                object.mark = false;
+                if alive {
+                    bytes += object.slot.size();
+                }
+                alive
+            });
+            swept += count - shard.len();
+            surviving += shard.len();
+        }
+        Ok(CollectStats {
+            kind: CollectKind::Full,
+            marked,
+            swept,
+            surviving,
+            mark_duration: sweep_start - start,
+            sweep_duration: sweep_start.elapsed(),
+            step_count: 1,
+            max_pause: start.elapsed(),
+            slot_max: self.slot_max(),
+            bytes,
+        })
+    }
+}
+
+impl<T: Keep> Drop for Pause<'_, T> {
+    /// Perform the collecting pass requested by paused allocation if this is the last alive
+    /// `Pause`. The error thrown by it is ignored, and would be thrown by the next collecting
+    /// pass again.
+    fn drop(&mut self) {
+        let collector = self.collector;
+        let last = {
+            let mut pauses = collector.pauses.lock().unwrap();
+            let id = thread::current().id();
+            let count = pauses.get_mut(&id).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                pauses.remove(&id);
+            }
+            pauses.is_empty()
+        };
+        drop(self.guard.take());
+        if last && collector.requested.swap(false, Ordering::SeqCst) {
+            let _ = collector.collect();
+        }
+    }
+}
+
+impl<T: Keep> fmt::Debug for Pause<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pause")
+    }
+}
+
+impl<T> fmt::Debug for ShardedCollector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ShardedCollector {{ shards: {}, slot_max: {} }}",
+            self.shards.len(),
+            self.slot_max()
+        )
+    }
+}
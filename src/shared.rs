@@ -0,0 +1,342 @@
+//!
+//! `SharedCollector<T>` wraps a `Collector<Slot<T>>` in a mutex, so it could be shared between
+//! threads with `Arc`, and provides blocking `take`. The mutex is released before current thread
+//! is parked on a busy slot, and it is held only during each method, so the caveats of `slot`
+//! module still apply. Use `SharedCollector::with` to perform several operations while holding
+//! the lock, e.g. allocating an object and filling it into its parent.
+//!
+//! Forgetting to fill a taken object back, e.g. on an early return, leaves the slot busy forever
+//! and every thread taking it later blocks forever. `SharedCollector::take_guard` returns a
+//! `TakeGuard` instead, which fills the object back when it is dropped.
+//!
+//! In async tasks, use `SharedCollector::take_async` instead of `take`, which returns a future
+//! waiting for the object without blocking current thread.
+//!
+//! `std::sync::Mutex` is used by default. Other mutexes could be used by implementing `Lock`
+//! for them and creating the collector with `SharedCollector::from_lock`.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use std::thread;
+//! use hulunbuir::{Address, Keep};
+//! use hulunbuir::shared::SharedCollector;
+//!
+//! struct Counter(usize);
+//!
+//! impl Keep for Counter {
+//!     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+//! }
+//!
+//! fn main() {
+//!     let collector = Arc::new(SharedCollector::new(16));
+//!     let counter = collector.allocate(Counter(0)).unwrap();
+//!     collector.with(|collector| collector.set_root(counter.clone()));
+//!     let handles: Vec<_> = (0..4)
+//!         .map(|_| {
+//!             let collector = Arc::clone(&collector);
+//!             let counter = counter.clone();
+//!             thread::spawn(move || {
+//!                 for _ in 0..100 {
+//!                     // block until no other thread is using the counter
+//!                     let mut value = collector.take(&counter).unwrap();
+//!                     value.0 += 1;
+//!                     collector.fill(&counter, value).unwrap();
+//!                 }
+//!             })
+//!         })
+//!         .collect();
+//!     for handle in handles {
+//!         handle.join().unwrap();
+//!     }
+//!     assert_eq!(collector.take(&counter).unwrap().0, 400);
+//! }
+//! ```
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use crate::{
+    error::Error,
+    slot::{Slot, Take},
+    stats::CollectStats,
+    Address, Collector, Keep,
+};
+
+/// Mutex which could protect a collector in `SharedCollector`.
+pub trait Lock<T> {
+    /// Lock the mutex and call `f` with the protected value, and release the mutex before
+    /// returning.
+    fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R;
+}
+
+impl<T> Lock<T> for Mutex<T> {
+    fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.lock().unwrap())
+    }
+}
+
+/// Collector shared between threads with blocking `take`. See module level document for
+/// details.
+#[derive(Debug)]
+pub struct SharedCollector<T, L = Mutex<Collector<Slot<T>>>> {
+    lock: L,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> SharedCollector<T> {
+    /// Create a collector with `slot_max` slots protected by `std::sync::Mutex`.
+    pub fn new(slot_max: usize) -> Self {
+        Self::from_lock(Mutex::new(Collector::new(slot_max)))
+    }
+}
+
+impl<T, L: Lock<Collector<Slot<T>>>> SharedCollector<T, L> {
+    /// Create a shared collector with a `lock` protecting the collector.
+    pub fn from_lock(lock: L) -> Self {
+        Self {
+            lock,
+            marker: PhantomData,
+        }
+    }
+
+    /// Lock the collector and call `f` with it, and return what `f` returns.
+    pub fn with<R, F: FnOnce(&mut Collector<Slot<T>>) -> R>(&self, f: F) -> R {
+        self.lock.with(f)
+    }
+}
+
+impl<T: Keep, L: Lock<Collector<Slot<T>>>> SharedCollector<T, L> {
+    /// Create a new managed object with `value` in slot. See `Collector::allocate` for details.
+    pub fn allocate(&self, value: T) -> Result<Address, Error> {
+        self.with(|collector| collector.allocate(Slot::new(value)))
+    }
+
+    /// Take the object at `address` out. If it is taken by others, release the lock and block
+    /// current thread until it is filled back, and try again. See `Collector::take` for details.
+    pub fn take(&self, address: &Address) -> Result<T, Error> {
+        loop {
+            match self.with(|collector| collector.take(address))? {
+                Take::Free(value) => return Ok(value),
+                Take::Busy(parker) => parker.park(),
+            }
+        }
+    }
+
+    /// Return a future which takes the object at `address` out. If it is taken by others, the
+    /// current task is woken when it is filled back. The future does not depend on any
+    /// particular executor. See `Collector::poll_take` for details.
+    ///
+    /// ```
+    /// # use std::future::Future;
+    /// # use std::pin::pin;
+    /// # use std::sync::Arc;
+    /// # use std::task::{Context, Poll, Wake};
+    /// # use std::thread::{self, Thread};
+    /// # use hulunbuir::{Address, Keep};
+    /// # use hulunbuir::shared::SharedCollector;
+    /// # struct Counter(usize);
+    /// # impl Keep for Counter {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// # struct Unpark(Thread);
+    /// # impl Wake for Unpark {
+    /// #     fn wake(self: Arc<Self>) {
+    /// #         self.0.unpark()
+    /// #     }
+    /// # }
+    /// # fn block_on<F: Future>(future: F) -> F::Output {
+    /// #     let mut future = pin!(future);
+    /// #     let waker = Arc::new(Unpark(thread::current())).into();
+    /// #     let mut cx = Context::from_waker(&waker);
+    /// #     loop {
+    /// #         match future.as_mut().poll(&mut cx) {
+    /// #             Poll::Ready(output) => return output,
+    /// #             Poll::Pending => thread::park(),
+    /// #         }
+    /// #     }
+    /// # }
+    /// let collector = Arc::new(SharedCollector::new(16));
+    /// let counter = collector.allocate(Counter(0)).unwrap();
+    /// let mut value = collector.take(&counter).unwrap();
+    /// let filler = {
+    ///     let collector = Arc::clone(&collector);
+    ///     let counter = counter.clone();
+    ///     thread::spawn(move || {
+    ///         value.0 += 1;
+    ///         collector.fill(&counter, value).unwrap();
+    ///     })
+    /// };
+    /// let value = block_on(collector.take_async(&counter)).unwrap();
+    /// assert_eq!(value.0, 1);
+    /// filler.join().unwrap();
+    /// ```
+    pub fn take_async(&self, address: &Address) -> TakeFuture<'_, T, L> {
+        TakeFuture {
+            collector: self,
+            address: address.to_owned(),
+        }
+    }
+
+    /// Take the object at `address` out as `SharedCollector::take`, and return a guard which
+    /// fills it back when dropped.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Error, Keep};
+    /// # use hulunbuir::shared::SharedCollector;
+    /// # struct Counter(usize);
+    /// # impl Keep for Counter {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// fn increase(collector: &SharedCollector<Counter>, counter: &Address) -> Result<(), Error> {
+    ///     let mut value = collector.take_guard(counter)?;
+    ///     value.0 += 1;
+    ///     if value.0 > 1 {
+    ///         return Err(Error::InvalidAddress(counter.to_owned()));
+    ///     }
+    ///     Ok(())
+    /// }
+    ///
+    /// let collector = SharedCollector::new(16);
+    /// let counter = collector.allocate(Counter(0)).unwrap();
+    /// assert!(increase(&collector, &counter).is_ok());
+    /// assert!(increase(&collector, &counter).is_err());
+    /// assert_eq!(collector.take(&counter).unwrap().0, 2);
+    /// ```
+    pub fn take_guard(&self, address: &Address) -> Result<TakeGuard<'_, T, L>, Error> {
+        Ok(TakeGuard {
+            collector: self,
+            address: address.to_owned(),
+            value: Some(self.take(address)?),
+        })
+    }
+
+    /// Fill the hole at `address` with `value`, which wakes up threads blocked on taking it.
+    /// See `Collector::fill` for details.
+    pub fn fill(&self, address: &Address, value: T) -> Result<(), Error> {
+        self.with(|collector| collector.fill(address, value))
+    }
+
+    /// Clean up all dead objects. See `Collector::collect` for details.
+    pub fn collect(&self) -> Result<CollectStats, Error> {
+        self.with(Collector::collect)
+    }
+}
+
+/// Object taken out by `SharedCollector::take_guard`, which is filled back when the guard is
+/// dropped. Errors of filling on dropping are ignored.
+///
+/// Filling locks the collector, so a guard must never be dropped while current thread is
+/// holding the lock, e.g. inside the closure passed to `SharedCollector::with`, which is a dead
+/// lock. Call `TakeGuard::into_inner` before locking, or use `TakeGuard::fill_with` instead.
+#[derive(Debug)]
+pub struct TakeGuard<'a, T: Keep, L: Lock<Collector<Slot<T>>>> {
+    collector: &'a SharedCollector<T, L>,
+    address: Address,
+    value: Option<T>,
+}
+
+impl<'a, T: Keep, L: Lock<Collector<Slot<T>>>> TakeGuard<'a, T, L> {
+    /// Return the address of taken object.
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Fill the hole with `value` in place of the taken object, which is dropped.
+    pub fn fill(mut self, value: T) -> Result<(), Error> {
+        self.value = None;
+        self.collector.fill(&self.address, value)
+    }
+
+    /// Lock the collector, call `f` with it and the taken object, and fill the object back
+    /// before releasing the lock. Use this to perform other operations in the same lock
+    /// holding, e.g. allocating an object and storing its address into the taken object. If
+    /// `f` panics, the object is dropped and the hole is left unfilled.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Keep};
+    /// # use hulunbuir::shared::SharedCollector;
+    /// # use hulunbuir::slot::Slot;
+    /// # struct ListNode(Option<Address>);
+    /// # impl Keep for ListNode {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+    /// #         if let Some(tail) = &self.0 {
+    /// #             keep(tail)
+    /// #         }
+    /// #     }
+    /// # }
+    /// let collector = SharedCollector::new(16);
+    /// let head = collector.allocate(ListNode(None)).unwrap();
+    /// collector.with(|collector| collector.set_root(head.clone()));
+    /// let node = collector.take_guard(&head).unwrap();
+    /// node.fill_with(|collector, node| {
+    ///     let tail = collector.allocate(Slot::new(ListNode(None))).unwrap();
+    ///     node.0 = Some(tail);
+    /// })
+    /// .unwrap();
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.with(|collector| collector.alive_count()), 2);
+    /// ```
+    pub fn fill_with<R, F: FnOnce(&mut Collector<Slot<T>>, &mut T) -> R>(
+        mut self,
+        f: F,
+    ) -> Result<R, Error> {
+        let mut value = self.value.take().unwrap();
+        let address = &self.address;
+        self.collector.with(|collector| {
+            let result = f(collector, &mut value);
+            collector.fill(address, value)?;
+            Ok(result)
+        })
+    }
+
+    /// Return the taken object without filling it back, so it must be filled manually, e.g.
+    /// with `Collector::fill` in `SharedCollector::with` along with other operations. Call
+    /// this before locking the collector, since the guard must not be dropped with the lock
+    /// held.
+    pub fn into_inner(mut self) -> T {
+        self.value.take().unwrap()
+    }
+}
+
+impl<'a, T: Keep, L: Lock<Collector<Slot<T>>>> Deref for TakeGuard<'a, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<'a, T: Keep, L: Lock<Collector<Slot<T>>>> DerefMut for TakeGuard<'a, T, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().unwrap()
+    }
+}
+
+impl<'a, T: Keep, L: Lock<Collector<Slot<T>>>> Drop for TakeGuard<'a, T, L> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            let _ = self.collector.fill(&self.address, value);
+        }
+    }
+}
+
+/// Future returned by `SharedCollector::take_async`.
+#[derive(Debug)]
+pub struct TakeFuture<'a, T, L> {
+    collector: &'a SharedCollector<T, L>,
+    address: Address,
+}
+
+impl<'a, T: Keep, L: Lock<Collector<Slot<T>>>> Future for TakeFuture<'a, T, L> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let address = &self.address;
+        self.collector
+            .with(|collector| collector.poll_take(address, cx))
+    }
+}
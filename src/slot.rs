@@ -110,8 +110,14 @@
 //!
 //! [1]: https://docs.rs/crossbeam/0.7.2/crossbeam/sync/struct.Parker.html
 //!
-//! The `wait` function above may be idiomatic, but I cannot find a way to provide it because
-//! I have no idea what kind of mutex you prefer.
+//! The `wait` function above is provided as `SharedCollector::take` in `shared` module, which
+//! works with `std::sync::Mutex` by default, and with any other mutex implementing
+//! `shared::Lock`.
+//!
+//! Blocking a thread is not acceptable inside async tasks. `Collector::poll_take` registers
+//! the `Waker` of current task instead of returning a parker, and the task is woken by
+//! `Collector::fill` as well. `SharedCollector::take_async` wraps it into a future, which
+//! works with any executor.
 //!
 //! # Disadvantage on using `Slot`
 //!
@@ -129,6 +135,9 @@
 //! like `Slot` for it!
 //!
 
+use std::mem;
+use std::task::{Context, Poll, Waker};
+
 use crate::{error::Error, Address, Collector, Keep};
 
 use crossbeam::sync::{Parker as ParkerPriv, Unparker};
@@ -139,7 +148,9 @@ enum SlotPriv<T> {
     Free(T),
     Busy {
         keep: Vec<Address>,
+        size: usize,
         unparkers: Vec<Unparker>,
+        wakers: Vec<Waker>,
     },
 }
 
@@ -153,6 +164,22 @@ impl<T> Slot<T> {
     pub fn new(value: T) -> Self {
         Self(SlotPriv::Free(value))
     }
+
+    /// Return a reference to the value, or `None` if it is taken out.
+    pub fn get(&self) -> Option<&T> {
+        match &self.0 {
+            SlotPriv::Free(value) => Some(value),
+            SlotPriv::Busy { .. } => None,
+        }
+    }
+
+    /// Return a mutable reference to the value, or `None` if it is taken out.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        match &mut self.0 {
+            SlotPriv::Free(value) => Some(value),
+            SlotPriv::Busy { .. } => None,
+        }
+    }
 }
 
 impl<T: Keep> Keep for Slot<T> {
@@ -166,6 +193,51 @@ impl<T: Keep> Keep for Slot<T> {
             }
         }
     }
+
+    fn size(&self) -> usize {
+        match &self.0 {
+            SlotPriv::Free(value) => value.size(),
+            SlotPriv::Busy { size, .. } => *size,
+        }
+    }
+
+    fn label(&self) -> Option<String> {
+        match &self.0 {
+            SlotPriv::Free(value) => value.label(),
+            SlotPriv::Busy { .. } => Some("(taken)".to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Slot<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            SlotPriv::Free(value) => value.serialize(serializer),
+            SlotPriv::Busy { .. } => Err(serde::ser::Error::custom("slot is taken")),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Slot<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: crate::snapshot::Relocate> crate::snapshot::Relocate for Slot<T> {
+    fn relocate<F: FnMut(&mut Address)>(&mut self, mut f: F) {
+        match &mut self.0 {
+            SlotPriv::Free(value) => value.relocate(f),
+            SlotPriv::Busy { keep, .. } => {
+                for address in keep {
+                    f(address);
+                }
+            }
+        }
+    }
 }
 
 /// The result of trying to take an object out.
@@ -177,49 +249,93 @@ pub enum Take<T> {
     Busy(Parker),
 }
 
-impl<T: Keep> Collector<Slot<T>> {
-    /// Take the object at `address` out and leave a hole there. `Error::InvalidAddress`
-    /// will be thrown if there's no alive object at `address`.
-    pub fn take(&mut self, address: &Address) -> Result<Take<T>, Error> {
-        let mut keep = Vec::new();
-        match &mut self
-            .slots
-            .get_mut(&address)
-            .ok_or(Error::InvalidAddress)?
-            .content
-            .0
-        {
-            SlotPriv::Free(value) => value.with_keep(|address| keep.push(address.to_owned())),
-            SlotPriv::Busy { unparkers, .. } => {
-                let parker = Parker::new();
-                unparkers.push(parker.unparker().to_owned());
-                return Ok(Take::Busy(parker));
+impl<T: Keep> Slot<T> {
+    /// Take the value out and leave a hole caching its kept addresses and size. If the value is
+    /// already taken, register a parker which will be unparked by the next filling.
+    pub(crate) fn take_value(&mut self) -> Take<T> {
+        if let SlotPriv::Busy { unparkers, .. } = &mut self.0 {
+            let parker = Parker::new();
+            unparkers.push(parker.unparker().to_owned());
+            return Take::Busy(parker);
+        }
+        Take::Free(self.take_free())
+    }
+
+    /// Take the value out as `Slot::take_value`. If the value is already taken, register the
+    /// waker of `cx` which will be woken by the next filling.
+    pub(crate) fn poll_take_value(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        if let SlotPriv::Busy { wakers, .. } = &mut self.0 {
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
             }
+            return Poll::Pending;
+        }
+        Poll::Ready(self.take_free())
+    }
+
+    fn take_free(&mut self) -> T {
+        let mut keep = Vec::new();
+        if let SlotPriv::Free(value) = &self.0 {
+            value.with_keep(|address| keep.push(address.to_owned()));
         }
-        let busy = Slot(SlotPriv::Busy {
+        let busy = SlotPriv::Busy {
             keep,
+            size: self.size(),
             unparkers: Vec::new(),
-        });
-        match self.replace(address, busy)?.0 {
-            SlotPriv::Free(value) => Ok(Take::Free(value)),
-            _ => unreachable!(),
+            wakers: Vec::new(),
+        };
+        match mem::replace(&mut self.0, busy) {
+            SlotPriv::Free(value) => value,
+            SlotPriv::Busy { .. } => unreachable!(),
         }
     }
+
+    /// Fill the hole with `value`, and unpark all waiting threads and wake all waiting tasks. If
+    /// the value is not taken, give `value` back.
+    pub(crate) fn fill_value(&mut self, value: T) -> Result<(), T> {
+        if let SlotPriv::Free(_) = &self.0 {
+            return Err(value);
+        }
+        if let SlotPriv::Busy {
+            unparkers, wakers, ..
+        } = mem::replace(&mut self.0, SlotPriv::Free(value))
+        {
+            for unparker in unparkers {
+                unparker.unpark();
+            }
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+        Ok(())
+    }
 }
 
-impl<T> Collector<Slot<T>> {
+impl<T: Keep> Collector<Slot<T>> {
+    /// Take the object at `address` out and leave a hole there. `Error::InvalidAddress`
+    /// will be thrown if there's no alive object at `address`.
+    pub fn take(&mut self, address: &Address) -> Result<Take<T>, Error> {
+        self.update(address, Slot::take_value)
+    }
+
+    /// Take the object at `address` out like `Collector::take`, but instead of returning a
+    /// parker when the object is used by others, register the waker of `cx` which will be woken
+    /// when the object is filled back, and return `Poll::Pending`. `Error::InvalidAddress` will
+    /// be thrown if there's no alive object at `address`.
+    pub fn poll_take(&mut self, address: &Address, cx: &mut Context<'_>) -> Poll<Result<T, Error>> {
+        match self.update(address, |slot| slot.poll_take_value(cx)) {
+            Ok(Poll::Ready(value)) => Poll::Ready(Ok(value)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
     /// Fill the hole at `address` with `value`. If the address does not contain a hole of
     /// an alive object, `Error::InvalidAddress` will be thrown. If there is already a not-in-used
-    /// object at `address`, then `Error::DuplicatedFilling` will be thrown.
+    /// object at `address`, then `Error::DuplicatedFilling` will be thrown and the object is
+    /// left untouched.
     pub fn fill(&mut self, address: &Address, value: T) -> Result<(), Error> {
-        match self.replace(address, Slot(SlotPriv::Free(value)))?.0 {
-            SlotPriv::Free(_) => Err(Error::DuplicatedFilling),
-            SlotPriv::Busy { unparkers, .. } => {
-                for unparker in unparkers {
-                    unparker.unpark();
-                }
-                Ok(())
-            }
-        }
+        self.update(address, |slot| slot.fill_value(value))?
+            .map_err(|_| Error::DuplicatedFilling(address.to_owned()))
     }
 }
@@ -0,0 +1,263 @@
+//!
+//! With `serde` feature enabled, `Collector<T>` and `Address` implement `Serialize` and
+//! `Deserialize` as long as `T` does, so a running collector could be saved to disk and loaded
+//! later, e.g. to checkpoint an interpreter session. `Slot<T>` is also serializable, but
+//! serializing a taken slot fails.
+//!
+//! A saved collector contains every slot (vacant ones included, so that addresses keep their
+//! meaning after loading), the root set, the root object and `slot_max`. Objects in
+//! finalization queue and registered finalizers are saved as well. Ephemeron tables, policy,
+//! hooks, statistics, generational mode and any collecting pass in progress are not saved,
+//! and the loaded collector starts with the defaults. Loading fails if root set or any object
+//! refers to an invalid address, or any address in root set is added zero times.
+//!
+//! A loaded collector could be merged into another non-empty collector with
+//! `Collector::import`, which allocates every object again and rewrites the addresses stored
+//! in objects through `Relocate` trait.
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use hulunbuir::{Address, Collector, Keep};
+//! use hulunbuir::snapshot::Relocate;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct ListNode(i32, Option<Address>);
+//!
+//! impl Keep for ListNode {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         if let Some(tail) = &self.1 {
+//!             keep(tail)
+//!         }
+//!     }
+//! }
+//!
+//! impl Relocate for ListNode {
+//!     fn relocate<F: FnMut(&mut Address)>(&mut self, mut relocate: F) {
+//!         if let Some(tail) = &mut self.1 {
+//!             relocate(tail)
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut collector = Collector::new(128);
+//!     let tail = collector.allocate(ListNode(1, None)).unwrap();
+//!     let head = collector.allocate(ListNode(0, Some(tail))).unwrap();
+//!     collector.set_root(head.clone());
+//!     let saved = serde_json::to_string(&collector).unwrap();
+//!
+//!     let loaded: Collector<ListNode> = serde_json::from_str(&saved).unwrap();
+//!     assert_eq!(loaded.root(), &Some(head.clone()));
+//!     assert_eq!(loaded.get(&head).unwrap().0, 0);
+//!
+//!     let mut other = Collector::new(128);
+//!     other.allocate(ListNode(2, None)).unwrap();
+//!     let moved = other.import(loaded).unwrap();
+//!     let new_head = &moved[&head];
+//!     let new_tail = other.get(new_head).unwrap().1.clone().unwrap();
+//!     assert_eq!(other.get(&new_tail).unwrap().0, 1);
+//!     // imported root set is merged into the existing one
+//!     other.collect().unwrap();
+//!     assert_eq!(other.alive_count(), 2);
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{error::Error, heap::Heap, Address, Collector, Keep, Slot, WeakAddress};
+
+/// Required trait for importing objects into another collector.
+pub trait Relocate {
+    /// When this method is called, it should calls back `relocate` with every address stored
+    /// in this object, including the ones passed to `Keep::with_keep` and weak addresses, one
+    /// per calling. `relocate` rewrites the address in place. Weak addresses implement this
+    /// trait as well, so they could be relocated with `WeakAddress::relocate`.
+    fn relocate<F: FnMut(&mut Address)>(&mut self, relocate: F);
+}
+
+impl Relocate for WeakAddress {
+    fn relocate<F: FnMut(&mut Address)>(&mut self, mut relocate: F) {
+        relocate(&mut self.0)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<S> {
+    slot_max: usize,
+    root: Option<Address>,
+    roots: Vec<(Address, usize)>,
+    finalizable: Vec<Address>,
+    entries: Vec<Entry<S>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<S> {
+    generation: usize,
+    object: Option<Object<S>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Object<S> {
+    finalize: bool,
+    content: S,
+}
+
+impl<T: Serialize> Serialize for Collector<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Snapshot {
+            slot_max: self.slot_max,
+            root: self.root.clone(),
+            roots: self
+                .roots
+                .iter()
+                .map(|(address, count)| (address.to_owned(), *count))
+                .collect(),
+            finalizable: self.finalizable.clone(),
+            entries: self
+                .slots
+                .entries()
+                .map(|(generation, slot)| Entry {
+                    generation,
+                    object: slot.map(|slot| Object {
+                        finalize: slot.finalize,
+                        content: &slot.content,
+                    }),
+                })
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Keep> Deserialize<'de> for Collector<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::<T>::deserialize(deserializer)?;
+        let mut collector = Self::new(snapshot.slot_max);
+        collector.slots = Heap::from_entries(snapshot.entries.into_iter().map(|entry| {
+            let slot = entry.object.map(|object| Slot {
+                mark: false,
+                young: false,
+                finalize: object.finalize,
+                size: object.content.size(),
+                content: object.content,
+            });
+            (entry.generation, slot)
+        }));
+        if collector.slots.len() > collector.slot_max {
+            return Err(de::Error::custom(format!(
+                "{} objects exceed slot_max {}",
+                collector.slots.len(),
+                collector.slot_max
+            )));
+        }
+        collector.roots = snapshot.roots.into_iter().collect();
+        collector.root = snapshot.root;
+        collector.finalizable = snapshot.finalizable;
+        collector.bytes = collector.slots.iter().map(|(_, slot)| slot.size).sum();
+        collector.finalizer_count = collector
+            .slots
+            .iter()
+            .filter(|(_, slot)| slot.finalize)
+            .count();
+        collector.validate().map_err(de::Error::custom)?;
+        Ok(collector)
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Check that every address in root set, finalization queue and objects is valid, and
+    /// every address in root set is added at least once, and throw the first invalid one.
+    fn validate(&self) -> Result<(), Error> {
+        for (address, count) in &self.roots {
+            if *count == 0 {
+                return Err(Error::InvalidRoot(address.to_owned()));
+            }
+        }
+        if let Some(root) = &self.root {
+            if self.slots.get(root).is_none() {
+                return Err(Error::InvalidRoot(root.to_owned()));
+            }
+        }
+        match self.verify().into_iter().next() {
+            Some(violation) => Err(violation.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Move every object of `other` into this collector, and return the new address of each
+    /// moved object keyed by its address in `other`. Addresses stored in moved objects are
+    /// rewritten with `Relocate::relocate`, and the ones referring to no object in `other`, such
+    /// as stale weak addresses, are rewritten to an address that is always invalid. Root set and
+    /// finalization queue of `other` are merged into this collector's. The root object of this
+    /// collector is not changed, while the one of `other` stays in root set.
+    ///
+    /// If there are not enough slots for all objects, throw `Error::OutOfSlots`. If the total
+    /// size of objects would exceed the byte budget, throw `Error::OutOfMemory`. If `other`
+    /// refers to any invalid address, throw `Error::InvalidRoot` or `Error::DanglingEdge`. In
+    /// all cases nothing is moved. See module `snapshot` for details.
+    pub fn import(&mut self, other: Collector<T>) -> Result<HashMap<Address, Address>, Error>
+    where
+        T: Relocate,
+    {
+        let status = self.heap_status();
+        if status.alive_count + other.slots.len() > status.slot_max {
+            return Err(Error::OutOfSlots {
+                alive_count: status.alive_count,
+                slot_max: status.slot_max,
+            });
+        }
+        if status.bytes + other.bytes > status.byte_max {
+            return Err(Error::OutOfMemory {
+                size: other.bytes,
+                bytes: status.bytes,
+                byte_max: status.byte_max,
+            });
+        }
+        other.validate()?;
+        let Collector {
+            mut slots,
+            roots,
+            finalizable,
+            ..
+        } = other;
+        let mut moved = HashMap::new();
+        let addresses: Vec<_> = slots.iter().map(|(address, _)| address).collect();
+        for address in addresses {
+            let slot = slots.remove(&address).unwrap();
+            if slot.finalize {
+                self.finalizer_count += 1;
+            }
+            self.bytes += slot.size;
+            let new_address = self.slots.insert(Slot {
+                mark: self.marking.is_some(),
+                young: self.nursery.is_some(),
+                ..slot
+            });
+            if let Some(nursery) = &mut self.nursery {
+                nursery.young.push(new_address.clone());
+            }
+            self.touch(&new_address);
+            moved.insert(address, new_address);
+        }
+        for new_address in moved.values() {
+            let slot = self.slots.get_mut(new_address).unwrap();
+            slot.content.relocate(|address| {
+                *address = match moved.get(address) {
+                    Some(new_address) => new_address.clone(),
+                    None => Address {
+                        index: usize::MAX,
+                        generation: 0,
+                    },
+                }
+            });
+        }
+        for (address, count) in roots {
+            *self.roots.entry(moved[&address].clone()).or_insert(0) += count;
+        }
+        self.finalizable
+            .extend(finalizable.iter().map(|address| moved[address].clone()));
+        Ok(moved)
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use log::info;
+
+use crate::{hook::Event, Collector};
+
+/// Kind of collecting pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectKind {
+    /// Collecting pass over the whole heap, by `Collector::collect` or `Collector::collect_step`.
+    Full,
+    /// Collecting pass over the young generation, by `Collector::collect_nursery`.
+    Nursery,
+}
+
+/// Statistics of a finished collecting pass.
+#[derive(Debug, Clone)]
+pub struct CollectStats {
+    /// Kind of the collecting pass.
+    pub kind: CollectKind,
+    /// Number of objects marked as alive. Old objects are not counted in nursery collecting
+    /// pass.
+    pub marked: usize,
+    /// Number of objects cleaned up.
+    pub swept: usize,
+    /// Number of objects in the heap after the collecting pass.
+    pub surviving: usize,
+    /// Total time spent on marking, across all steps.
+    pub mark_duration: Duration,
+    /// Time spent on sweeping.
+    pub sweep_duration: Duration,
+    /// Number of steps the collecting pass is split into. Always 1 if it is not incremental.
+    pub step_count: usize,
+    /// The longest time the collector is occupied by a single step.
+    pub max_pause: Duration,
+    /// Maximum number of objects after the collecting pass.
+    pub slot_max: usize,
+    /// Total size of objects in bytes after the collecting pass.
+    pub bytes: usize,
+}
+
+impl CollectStats {
+    /// Return the ratio of used slots after the collecting pass.
+    pub fn occupancy(&self) -> f32 {
+        self.surviving as f32 / self.slot_max as f32
+    }
+}
+
+/// Cumulative statistics of all collecting passes performed by a collector.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// Number of finished full collecting passes.
+    pub collections: usize,
+    /// Number of finished nursery collecting passes.
+    pub nursery_collections: usize,
+    /// Total number of objects cleaned up.
+    pub freed: usize,
+    /// The longest time the collector is occupied by a single step.
+    pub max_pause: Duration,
+}
+
+impl<T> Collector<T> {
+    /// Return cumulative statistics of all collecting passes performed by this collector.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let root = collector.allocate(Leaf).unwrap();
+    /// collector.set_root(root);
+    /// let _orphan = collector.allocate(Leaf).unwrap();
+    /// let stats = collector.collect().unwrap();
+    /// assert_eq!((stats.marked, stats.swept, stats.surviving), (1, 1, 1));
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.statistics().collections, 2);
+    /// assert_eq!(collector.statistics().freed, 1);
+    /// ```
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    pub(crate) fn record(&mut self, stats: &CollectStats) {
+        match stats.kind {
+            CollectKind::Full => self.statistics.collections += 1,
+            CollectKind::Nursery => self.statistics.nursery_collections += 1,
+        }
+        self.statistics.freed += stats.swept;
+        self.statistics.max_pause = self.statistics.max_pause.max(stats.max_pause);
+        self.hooks.fire(&Event::AfterCollect(stats));
+
+        info!(
+            target: "hulunbuir",
+            "{} collected in {} ms ({} steps), {} objects freed, {:.2}% of available slots used",
+            match stats.kind {
+                CollectKind::Full => "garbage",
+                CollectKind::Nursery => "nursery",
+            },
+            (stats.mark_duration + stats.sweep_duration).as_micros() as f32 / 1000.0,
+            stats.step_count,
+            stats.swept,
+            stats.occupancy() * 100.0
+        );
+    }
+}
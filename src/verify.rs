@@ -0,0 +1,152 @@
+//!
+//! A collecting pass stops with `Error::InvalidRoot` or `Error::DanglingEdge` as soon as it
+//! meets an address of no object, which is usually caused by a buggy `Keep::with_keep`
+//! implementation, or by storing an address from another collector. `Collector::verify` walks
+//! the whole heap without changing anything and reports every such address, along with the
+//! object holding it.
+//!
+//! With `Collector::set_verify`, the collector verifies itself before and after every
+//! collecting pass and panics on any violation. The check only runs in debug builds, so it
+//! could be left enabled in release builds at no cost.
+//!
+//! ```rust
+//! use hulunbuir::{Address, Collector, Keep};
+//!
+//! struct ListNode(Option<Address>);
+//!
+//! impl Keep for ListNode {
+//!     fn with_keep<F: FnMut(&Address)>(&self, mut keep: F) {
+//!         if let Some(tail) = &self.0 {
+//!             keep(tail)
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut collector = Collector::new(128);
+//!     let stale = collector.allocate(ListNode(None)).unwrap();
+//!     collector.collect().unwrap();
+//!     let head = collector.allocate(ListNode(Some(stale.clone()))).unwrap();
+//!     collector.set_root(head.clone());
+//!     collector.add_root(stale.clone());
+//!
+//!     let violations = collector.verify();
+//!     assert_eq!(violations.len(), 2);
+//!     assert!(violations.iter().any(|violation| violation.is_root()));
+//!     assert!(violations.iter().any(|violation| {
+//!         violation.referrer == Some(head.clone()) && violation.target == stale
+//!     }));
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::{error::Error, Address, Collector, Keep};
+
+/// An address of no object found by `Collector::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Address of the object which keeps `target`, or `None` if `target` is in root set or
+    /// finalization queue.
+    pub referrer: Option<Address>,
+    /// The address of no object.
+    pub target: Address,
+}
+
+impl Violation {
+    /// Return `true` if the violation comes from root set or finalization queue.
+    pub fn is_root(&self) -> bool {
+        self.referrer.is_none()
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.referrer {
+            Some(referrer) => write!(f, "{} keeps invalid address {}", referrer, self.target),
+            None => write!(
+                f,
+                "root set or finalization queue contains invalid address {}",
+                self.target
+            ),
+        }
+    }
+}
+
+impl From<Violation> for Error {
+    fn from(violation: Violation) -> Self {
+        match violation.referrer {
+            Some(from) => Error::DanglingEdge {
+                from,
+                to: violation.target,
+            },
+            None => Error::InvalidRoot(violation.target),
+        }
+    }
+}
+
+impl<T> Collector<T> {
+    /// Verify this collector before and after every collecting pass if `verify` is `true`,
+    /// and panic if any violation is found. It has no effect in release builds. See module
+    /// `verify` for details.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+}
+
+impl<T: Keep> Collector<T> {
+    /// Check every address in root set, finalization queue and objects (dead objects that
+    /// have not been cleaned up included), and return all the addresses of no object. Return
+    /// an empty list if the heap is sound.
+    pub fn verify(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for address in self.trace_roots() {
+            if self.slots.get(address).is_none() {
+                violations.push(Violation {
+                    referrer: None,
+                    target: address.to_owned(),
+                });
+            }
+        }
+        for (referrer, slot) in self.slots.iter() {
+            slot.content.with_keep(|address| {
+                if self.slots.get(address).is_none() {
+                    violations.push(Violation {
+                        referrer: Some(referrer.clone()),
+                        target: address.to_owned(),
+                    });
+                }
+            });
+        }
+        violations
+    }
+
+    /// Build the error for invalid `address` met when collecting, by finding out where it
+    /// comes from.
+    pub(crate) fn invalid_reference(&self, address: Address) -> Error {
+        match self
+            .verify()
+            .into_iter()
+            .find(|violation| violation.target == address)
+        {
+            Some(violation) => violation.into(),
+            None => Error::InvalidAddress(address),
+        }
+    }
+
+    /// Panic with all violations if verification is enabled in a debug build.
+    pub(crate) fn debug_verify(&self, stage: &str) {
+        if !cfg!(debug_assertions) || !self.verify {
+            return;
+        }
+        let violations = self.verify();
+        if !violations.is_empty() {
+            let violations: Vec<_> = violations.iter().map(ToString::to_string).collect();
+            panic!(
+                "heap verification failed {} collecting: {}",
+                stage,
+                violations.join(", ")
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{error::Error, Address, Collector};
+
+/// Address token which does not keep the object alive.
+///
+/// Managed objects could store weak addresses without passing them to `keep` in
+/// `Keep::with_keep`, e.g. for parent back-pointers or observer lists. A weak address is
+/// invalidated once its object is collected, after which `Collector::upgrade` returns `None`.
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeakAddress(pub(crate) Address);
+
+impl<T> Collector<T> {
+    /// Create a weak address for object at `address`. If there's no object at `address`,
+    /// throw `Error::InvalidAddress`.
+    ///
+    /// ```
+    /// # use hulunbuir::{Address, Collector, Keep};
+    /// # struct Leaf;
+    /// # impl Keep for Leaf {
+    /// #     fn with_keep<F: FnMut(&Address)>(&self, _keep: F) {}
+    /// # }
+    /// let mut collector = Collector::new(16);
+    /// let root = collector.allocate(Leaf).unwrap();
+    /// let orphan = collector.allocate(Leaf).unwrap();
+    /// collector.set_root(root.clone());
+    /// let weak_root = collector.downgrade(&root).unwrap();
+    /// let weak_orphan = collector.downgrade(&orphan).unwrap();
+    /// collector.collect().unwrap();
+    /// assert_eq!(collector.upgrade(&weak_root), Some(root));
+    /// assert_eq!(collector.upgrade(&weak_orphan), None);
+    /// ```
+    pub fn downgrade(&self, address: &Address) -> Result<WeakAddress, Error> {
+        self.slots
+            .get(address)
+            .ok_or_else(|| Error::InvalidAddress(address.to_owned()))?;
+        Ok(WeakAddress(address.to_owned()))
+    }
+
+    /// Return the address of object referred by `weak`, or `None` if it has been collected.
+    ///
+    /// If a collecting pass is in progress, the object is taken as gray, so it will not be
+    /// collected by this pass even if the returned address is stored into a scanned object.
+    pub fn upgrade(&mut self, weak: &WeakAddress) -> Option<Address> {
+        self.slots.get(&weak.0)?;
+        if let Some(marking) = &mut self.marking {
+            marking.stack.push(weak.0.to_owned());
+        }
+        Some(weak.0.to_owned())
+    }
+}